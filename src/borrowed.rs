@@ -0,0 +1,212 @@
+use crate::error::ParseError;
+use crate::header::Header;
+use crate::parser::Parser;
+use crate::tree::{Item, Node, Root};
+use crate::{Contents, ParseOptions, Spans, Target};
+use std::borrow::Cow;
+use std::mem::{replace, take};
+
+///Result object borrowing from the parsed `&str`.
+///
+///Key and text are borrowed, only a key with escapes and a text with continuation lines are owned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorrowedTarget<'a> {
+    pub name: Cow<'a, str>,
+    pub text: Vec<Cow<'a, str>>,
+    pub value: Vec<BorrowedTarget<'a>>,
+}
+
+impl BorrowedTarget<'_> {
+    ///Copy to `Target`.
+    pub fn into_owned(self) -> Target {
+        Target {
+            name: self.name.into_owned(),
+            text: self.text.into_iter().map(Cow::into_owned).collect(),
+            value: self.value.into_iter().map(Self::into_owned).collect(),
+            spans: None,
+        }
+    }
+}
+
+impl From<BorrowedTarget<'_>> for Target {
+    fn from(target: BorrowedTarget<'_>) -> Self {
+        target.into_owned()
+    }
+}
+
+impl<'a> Item<Cow<'a, str>> for BorrowedTarget<'a> {
+    fn item(
+        name: Cow<'a, str>,
+        text: Vec<Cow<'a, str>>,
+        value: Vec<Self>,
+        _spans: Option<Spans>,
+    ) -> Self {
+        BorrowedTarget { name, text, value }
+    }
+}
+
+///Result document borrowing from the parsed `&str`, header lines are owned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorrowedDocument<'a> {
+    pub header: Vec<String>,
+    pub targets: Vec<BorrowedTarget<'a>>,
+}
+
+//key or text being accepted, borrowed while its chars are next to each other in the source
+enum Slice {
+    Empty,
+    Borrowed(usize, usize),
+    Owned(String),
+}
+
+impl Slice {
+    fn push(&mut self, src: &str, index: usize, c: char) {
+        let next = src[index..].starts_with(c);
+        match self {
+            Slice::Empty if next => *self = Slice::Borrowed(index, index + c.len_utf8()),
+            Slice::Borrowed(_, end) if next && *end == index => *end += c.len_utf8(),
+            Slice::Empty => *self = Slice::Owned(c.to_string()),
+            Slice::Borrowed(start, end) => {
+                let mut s = src[*start..*end].to_string();
+                s.push(c);
+                *self = Slice::Owned(s);
+            }
+            Slice::Owned(s) => s.push(c),
+        }
+    }
+
+    fn take<'a>(&mut self, src: &'a str) -> Cow<'a, str> {
+        match replace(self, Slice::Empty) {
+            Slice::Empty => Cow::Borrowed(""),
+            Slice::Borrowed(start, end) => Cow::Borrowed(&src[start..end]),
+            Slice::Owned(s) => Cow::Owned(s),
+        }
+    }
+}
+
+struct BorrowedBuilder<'a, T: FnMut(ParseError)> {
+    src: &'a str,
+    //byte index of the char being accepted
+    index: usize,
+    header: Header,
+    tree: Root<Cow<'a, str>>,
+    offset: usize,
+    key: Slice,
+    texts: Vec<Cow<'a, str>>,
+    text: Slice,
+    err_function: T,
+}
+
+impl<'a, T: FnMut(ParseError)> Contents for BorrowedBuilder<'a, T> {
+    type Item = BorrowedDocument<'a>;
+
+    fn get(self) -> Self::Item {
+        BorrowedDocument {
+            header: self.header.get(),
+            targets: self.tree.build(),
+        }
+    }
+
+    fn header(&mut self, header: &str) {
+        self.header.accept(header);
+    }
+
+    fn pre_key(&mut self, offset: usize) {
+        self.offset = offset;
+        self.key = Slice::Empty;
+    }
+
+    fn key(&mut self, c: char) {
+        self.key.push(self.src, self.index, c);
+    }
+
+    fn post_key(&mut self) {}
+
+    fn pre_text(&mut self) {
+        self.text = Slice::Empty;
+    }
+
+    fn text(&mut self, c: char) {
+        self.text.push(self.src, self.index, c);
+    }
+
+    fn text_array(&mut self) {
+        self.texts.push(self.text.take(self.src));
+    }
+
+    fn post_text(&mut self) {
+        self.texts.push(self.text.take(self.src));
+        let key = self.key.take(self.src);
+        let n = Node::new(self.offset, key, take(&mut self.texts));
+        self.tree.add(n);
+    }
+
+    fn error(&mut self, error: ParseError) {
+        (self.err_function)(error);
+    }
+}
+
+///Parse text format to `BorrowedDocument` without copying keys and texts, or all errors found.
+pub fn str_to_borrowed_document(s: &str) -> Result<BorrowedDocument<'_>, Vec<ParseError>> {
+    str_to_borrowed_document_with(s, &ParseOptions::default())
+}
+
+///Parse text format to `BorrowedDocument` with options, or all errors found.
+pub fn str_to_borrowed_document_with<'a>(
+    s: &'a str,
+    options: &ParseOptions,
+) -> Result<BorrowedDocument<'a>, Vec<ParseError>> {
+    let mut errors = Vec::new();
+    let mut parser = Parser::new(BorrowedBuilder {
+        src: s,
+        index: 0,
+        header: Header::new(),
+        tree: Root::new(options.ordered),
+        offset: 0,
+        key: Slice::Empty,
+        texts: Vec::new(),
+        text: Slice::Empty,
+        err_function: |e| errors.push(e),
+    });
+    parser.set_options(options);
+    for (i, c) in s.char_indices() {
+        parser.contents_mut().index = i;
+        parser.accept(c);
+        if parser.stopped() {
+            break;
+        }
+    }
+    parser.contents_mut().index = s.len();
+    parser.finish();
+    let rst = parser.contents().get();
+    if errors.is_empty() {
+        Ok(rst)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chars_to_document;
+
+    #[test]
+    fn test_borrowed() {
+        let s = "#h\n0~a:x\n+y\n0~b\\:c:1\r\n|2\n1~d:\u{e9}\n0~a:z\n";
+        let d = str_to_borrowed_document(s).unwrap();
+        let t = &d.targets;
+        assert!(matches!(t[0].name, Cow::Borrowed("a")));
+        assert_eq!(t[0].text, vec!["x", "y", "z"]);
+        assert!(t[0].text.iter().all(|t| matches!(t, Cow::Borrowed(_))));
+        assert!(matches!(t[1].name, Cow::Owned(_)));
+        assert!(matches!(t[1].text[0], Cow::Owned(_)));
+        assert!(matches!(t[1].value[0].text[0], Cow::Borrowed("\u{e9}")));
+
+        let owned = chars_to_document(s.chars()).unwrap();
+        assert_eq!(d.header, owned.header);
+        let targets: Vec<Target> = d.targets.into_iter().map(Target::from).collect();
+        assert_eq!(targets, owned.targets);
+        assert!(str_to_borrowed_document("0~a\n|b").is_err());
+    }
+}
@@ -1,7 +1,14 @@
-use crate::parser::{Parser, END};
+use crate::error::{ParseError, ReadError};
+use crate::header::Header;
+use crate::options::{OutputOptions, ParseOptions};
+use crate::parser::Parser;
+use crate::span::{Position, Span, Spans};
 use crate::tree::{Node, Root};
-use crate::xml::add_tag;
+use crate::utf8::Utf8Decoder;
+use crate::xml::{add_tag, add_tag_with_attribute, Layout};
 use std::collections::HashMap;
+use std::io::{ErrorKind, Read};
+use std::mem::take;
 
 ///Trait that is represents action when parse text.
 pub trait Contents {
@@ -11,6 +18,9 @@ pub trait Contents {
     ///Get result.
     fn get(self) -> Self::Item;
 
+    ///Accept a header line, without the leading number sign(#).
+    fn header(&mut self, _header: &str) {}
+
     ///Accept offset and prepare to accept key.
     fn pre_key(&mut self, offset: usize);
 
@@ -32,16 +42,29 @@ pub trait Contents {
     ///End of text.
     fn post_text(&mut self);
 
-    ///Wrong char in the row and number.
-    fn error(&mut self, row: usize, n: usize);
+    ///Wrong char, with the reason and position.
+    fn error(&mut self, error: ParseError);
+
+    ///Row and column of the char about to be accepted, called before each char.
+    fn position(&mut self, _row: usize, _column: usize) {}
 }
 
 ///Result object.
+///
+///`spans` is left out of `==`, so a document parsed with and without spans compares equal.
 #[derive(Clone, Debug)]
 pub struct Target {
     pub name: String,
     pub text: Vec<String>,
     pub value: Vec<Target>,
+    ///Source ranges, with `ParseOptions.spans`.
+    pub spans: Option<Box<Spans>>,
+}
+
+impl PartialEq for Target {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.text == other.text && self.value == other.value
+    }
 }
 
 impl Target {
@@ -50,83 +73,152 @@ impl Target {
             name,
             text: Vec::new(),
             value: Vec::new(),
+            spans: None,
         }
     }
 }
 
-struct Builder<T: FnMut(usize, usize)> {
+///Result document, header lines and objects.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    pub header: Vec<String>,
+    pub targets: Vec<Target>,
+}
+
+//source ranges of the key and texts being accepted
+#[derive(Default)]
+struct SpanRecorder {
+    position: Position,
+    key: Span,
+    text: Span,
+    texts: Vec<Span>,
+}
+
+impl SpanRecorder {
+    //empty span just after the current char
+    fn after(&self) -> Span {
+        let p = Position {
+            row: self.position.row,
+            column: self.position.column + 1,
+        };
+        Span { start: p, end: p }
+    }
+}
+
+struct Builder<T: FnMut(ParseError)> {
+    header: Header,
     tree: Root,
     offset: usize,
     key: String,
+    texts: Vec<String>,
     text: String,
+    spans: Option<SpanRecorder>,
     err_function: T,
 }
 
-impl<T: FnMut(usize, usize)> Contents for Builder<T> {
-    type Item = Vec<Target>;
+impl<T: FnMut(ParseError)> Contents for Builder<T> {
+    type Item = Document;
 
     fn get(self) -> Self::Item {
-        self.tree.build()
+        Document {
+            header: self.header.get(),
+            targets: self.tree.build(),
+        }
+    }
+
+    fn header(&mut self, header: &str) {
+        self.header.accept(header);
     }
 
     fn pre_key(&mut self, offset: usize) {
         self.offset = offset;
         self.key.clear();
+        if let Some(r) = &mut self.spans {
+            r.key.start = r.position;
+        }
     }
 
     fn key(&mut self, c: char) {
         self.key.push(c);
     }
 
-    fn post_key(&mut self) {}
+    fn post_key(&mut self) {
+        if let Some(r) = &mut self.spans {
+            r.key.end = r.position;
+            r.text = r.after();
+        }
+    }
 
     fn pre_text(&mut self) {
         self.text.clear();
+        if let Some(r) = &mut self.spans {
+            r.text.start = r.position;
+            r.text.end = r.position;
+        }
     }
 
     fn text(&mut self, c: char) {
         self.text.push(c);
+        if let Some(r) = &mut self.spans {
+            r.text.end = r.after().end;
+        }
     }
 
     fn text_array(&mut self) {
-        self.post_text();
-        self.pre_text();
+        self.texts.push(take(&mut self.text));
+        if let Some(r) = &mut self.spans {
+            r.texts.push(r.text);
+            r.text = r.after();
+        }
     }
 
     fn post_text(&mut self) {
-        let n = Node::new(self.offset, self.key.clone(), self.text.clone());
+        self.texts.push(take(&mut self.text));
+        let mut n = Node::new(self.offset, take(&mut self.key), take(&mut self.texts));
+        if let Some(r) = &mut self.spans {
+            r.texts.push(r.text);
+            n = n.with_spans(r.key, take(&mut r.texts));
+        }
         self.tree.add(n);
-        self.text.clear();
     }
 
-    fn error(&mut self, row: usize, n: usize) {
-        (self.err_function)(row, n);
+    fn error(&mut self, error: ParseError) {
+        (self.err_function)(error);
+    }
+
+    fn position(&mut self, row: usize, column: usize) {
+        if let Some(r) = &mut self.spans {
+            r.position = Position { row, column };
+        }
     }
 }
 
-impl<T: FnMut(usize, usize)> Builder<T> {
-    fn new(func: T) -> Self {
+impl<T: FnMut(ParseError)> Builder<T> {
+    fn new(func: T, options: &ParseOptions) -> Self {
         Builder {
-            tree: Root::new(),
+            header: Header::new(),
+            tree: Root::new(options.ordered),
             offset: 0,
             key: String::new(),
+            texts: Vec::new(),
             text: String::new(),
+            spans: options.spans.then(SpanRecorder::default),
             err_function: func,
         }
     }
 }
 
 ///Parse text format.
-pub fn parse_chars<T, S, R>(mut iter: S, contents: R) -> T
+pub fn parse_chars<T, S, R>(iter: S, contents: R) -> T
 where
     S: Iterator<Item = char>,
     R: Contents<Item = T>,
 {
     let mut parser = Parser::new(contents);
-    while let Some(c) = iter.next() {
+    for c in iter {
         parser.accept(c);
     }
-    parser.accept(END);
+    parser.finish();
     parser.contents().get()
 }
 
@@ -139,30 +231,185 @@ where
     for c in buf {
         parser.accept(*c);
     }
-    parser.accept(END);
+    parser.finish();
+    parser.contents().get()
+}
+
+//parse, stopping early with `ParseOptions.strict` or a limit
+fn parse_with<T: Contents>(
+    iter: impl Iterator<Item = char>,
+    contents: T,
+    options: &ParseOptions,
+) -> T::Item {
+    let mut parser = Parser::new(contents);
+    parser.set_options(options);
+    for c in iter {
+        parser.accept(c);
+        if parser.stopped() {
+            break;
+        }
+    }
+    parser.finish();
     parser.contents().get()
 }
 
-///Parse text format to `Vec<Target>`.
-pub fn chars_to_target<T>(iter: T, func: impl FnMut(usize, usize)) -> Vec<Target>
+//accept a char from `Utf8Decoder`, `None` is an invalid sequence
+fn accept_decoded<T: Contents>(parser: &mut Parser<T>, c: Option<char>) {
+    match c {
+        Some(c) => parser.accept(c),
+        None => parser.accept_invalid(),
+    }
+}
+
+///Parse text format to `Vec<Target>`. `func` receives the row and column of each wrong char.
+///
+///The default `Limits` apply: an entry nested more than 1024 levels deep stops the parser,
+///`func` receives its position, and it and all entries after it are dropped.
+pub fn chars_to_target<T>(iter: T, mut func: impl FnMut(usize, usize)) -> Vec<Target>
 where
     T: Iterator<Item = char>,
 {
-    parse_chars(iter, Builder::new(func))
+    let options = ParseOptions::default();
+    parse_chars(
+        iter,
+        Builder::new(|e: ParseError| func(e.row, e.column), &options),
+    )
+    .targets
 }
 
-///Parse text format to `Vec<Target>`.
-pub fn slice_to_target(buf: &[char], func: impl FnMut(usize, usize)) -> Vec<Target> {
-    parse_slice(buf, Builder::new(func))
+///Parse text format to `Vec<Target>`. `func` receives the row and column of each wrong char.
+///
+///The default `Limits` apply: an entry nested more than 1024 levels deep stops the parser,
+///`func` receives its position, and it and all entries after it are dropped.
+pub fn slice_to_target(buf: &[char], mut func: impl FnMut(usize, usize)) -> Vec<Target> {
+    let options = ParseOptions::default();
+    parse_slice(
+        buf,
+        Builder::new(|e: ParseError| func(e.row, e.column), &options),
+    )
+    .targets
+}
+
+///Parse text format to `Vec<Target>`, or all errors found.
+pub fn try_chars_to_target<T>(iter: T) -> Result<Vec<Target>, Vec<ParseError>>
+where
+    T: Iterator<Item = char>,
+{
+    chars_to_document(iter).map(|d| d.targets)
+}
+
+///Parse text format to `Vec<Target>`, or all errors found.
+pub fn try_slice_to_target(buf: &[char]) -> Result<Vec<Target>, Vec<ParseError>> {
+    slice_to_document(buf).map(|d| d.targets)
+}
+
+///Parse text format to `Document`, or all errors found.
+pub fn chars_to_document<T>(iter: T) -> Result<Document, Vec<ParseError>>
+where
+    T: Iterator<Item = char>,
+{
+    chars_to_document_with(iter, &ParseOptions::default())
+}
+
+///Parse text format to `Document` with options, or all errors found.
+pub fn chars_to_document_with<T>(
+    iter: T,
+    options: &ParseOptions,
+) -> Result<Document, Vec<ParseError>>
+where
+    T: Iterator<Item = char>,
+{
+    let mut errors = Vec::new();
+    let rst = parse_with(iter, Builder::new(|e| errors.push(e), options), options);
+    if errors.is_empty() {
+        Ok(rst)
+    } else {
+        Err(errors)
+    }
+}
+
+///Parse text format to `Document`, or all errors found.
+pub fn slice_to_document(buf: &[char]) -> Result<Document, Vec<ParseError>> {
+    slice_to_document_with(buf, &ParseOptions::default())
+}
+
+///Parse text format to `Document` with options, or all errors found.
+pub fn slice_to_document_with(
+    buf: &[char],
+    options: &ParseOptions,
+) -> Result<Document, Vec<ParseError>> {
+    let mut errors = Vec::new();
+    let rst = parse_with(
+        buf.iter().copied(),
+        Builder::new(|e| errors.push(e), options),
+        options,
+    );
+    if errors.is_empty() {
+        Ok(rst)
+    } else {
+        Err(errors)
+    }
+}
+
+///Parse UTF-8 text format to `Document`, or all errors found. A leading BOM is skipped.
+pub fn from_bytes(bytes: &[u8]) -> Result<Document, Vec<ParseError>> {
+    from_bytes_with(bytes, &ParseOptions::default())
+}
+
+///Parse UTF-8 text format to `Document` with options, or all errors found.
+pub fn from_bytes_with(bytes: &[u8], options: &ParseOptions) -> Result<Document, Vec<ParseError>> {
+    let mut errors = Vec::new();
+    let mut parser = Parser::new(Builder::new(|e| errors.push(e), options));
+    parser.set_options(options);
+    let mut decoder = Utf8Decoder::new();
+    decoder.decode(bytes, &mut |c| accept_decoded(&mut parser, c));
+    decoder.finish(&mut |c| accept_decoded(&mut parser, c));
+    parser.finish();
+    let rst = parser.contents().get();
+    if errors.is_empty() {
+        Ok(rst)
+    } else {
+        Err(errors)
+    }
+}
+
+///Parse UTF-8 text format from reader to `Document`, decoding as it reads. A leading BOM is skipped.
+pub fn from_reader<R: Read>(reader: R) -> Result<Document, ReadError> {
+    from_reader_with(reader, &ParseOptions::default())
+}
+
+///Parse UTF-8 text format from reader to `Document` with options.
+pub fn from_reader_with<R: Read>(
+    mut reader: R,
+    options: &ParseOptions,
+) -> Result<Document, ReadError> {
+    let mut errors = Vec::new();
+    let mut parser = Parser::new(Builder::new(|e| errors.push(e), options));
+    parser.set_options(options);
+    let mut decoder = Utf8Decoder::new();
+    let mut buf = [0; 8192];
+    while !parser.stopped() {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => decoder.decode(&buf[..n], &mut |c| accept_decoded(&mut parser, c)),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    decoder.finish(&mut |c| accept_decoded(&mut parser, c));
+    parser.finish();
+    let rst = parser.contents().get();
+    if errors.is_empty() {
+        Ok(rst)
+    } else {
+        Err(ReadError::Parse(errors))
+    }
 }
 
 ///Convert text format `Vec<Target>` to `HashMap`. use separator to join key.
 pub fn vec_to_map(vec: Vec<Target>, separator: &str) -> HashMap<String, Vec<String>> {
     let mut map = HashMap::new();
-    for target in vec {
-        let mut key_vec = Vec::new();
-        target_to_map(target, &mut key_vec, &mut map);
-    }
+    target_to_map(vec, &mut map);
     map.into_iter()
         .map(|(k, v)| (k.join(separator), v))
         .collect()
@@ -170,48 +417,103 @@ pub fn vec_to_map(vec: Vec<Target>, separator: &str) -> HashMap<String, Vec<Stri
 
 ///Convert text format `Target` to `HashMap`. use separator to join key.
 pub fn to_map(target: Target, separator: &str) -> HashMap<String, Vec<String>> {
-    let mut map = HashMap::new();
-    let mut key_vec = Vec::new();
-    target_to_map(target, &mut key_vec, &mut map);
-    map.into_iter()
-        .map(|(k, v)| (k.join(separator), v))
-        .collect()
+    vec_to_map(vec![target], separator)
 }
 
-fn target_to_map(
-    target: Target,
-    key_vec: &mut Vec<String>,
-    map: &mut HashMap<Vec<String>, Vec<String>>,
-) {
-    key_vec.push(target.name);
-    if target.text.len() > 0 {
-        map.insert(key_vec.to_vec(), target.text);
-    }
-    for c in target.value {
-        target_to_map(c, key_vec, map);
+//depth first with a stack of children iterators, one per key in key_vec plus the top level
+fn target_to_map(vec: Vec<Target>, map: &mut HashMap<Vec<String>, Vec<String>>) {
+    let mut key_vec = Vec::new();
+    let mut stack = vec![vec.into_iter()];
+    while let Some(iter) = stack.last_mut() {
+        match iter.next() {
+            Some(target) => {
+                key_vec.push(target.name);
+                if !target.text.is_empty() {
+                    map.insert(key_vec.to_vec(), target.text);
+                }
+                stack.push(target.value.into_iter());
+            }
+            None => {
+                stack.pop();
+                key_vec.pop();
+            }
+        }
     }
-    key_vec.pop();
 }
 
 ///Convert text format `Vec<Target>` to xml format.
+///
+///Text and attribute values are escaped. A name that is not a valid xml name is renamed,
+///every invalid char becomes underscore(_), and an underscore(_) is put before an invalid first char.
 pub fn vec_to_xml(vec: Vec<Target>) -> String {
+    vec_to_xml_with(vec, &OutputOptions::default())
+}
+
+///Convert text format `Vec<Target>` to xml format with output options, e.g. indented.
+pub fn vec_to_xml_with(vec: Vec<Target>, options: &OutputOptions) -> String {
     let mut xml = String::new();
-    for target in vec {
-        xml.push_str(&to_xml(target));
-    }
+    push_xml_vec(&mut xml, vec, Layout::new(options));
     xml
 }
 
 ///Convert text format `Target` to xml format.
 pub fn to_xml(target: Target) -> String {
+    to_xml_with(target, &OutputOptions::default())
+}
+
+///Convert text format `Target` to xml format with output options.
+pub fn to_xml_with(target: Target, options: &OutputOptions) -> String {
     let mut xml = String::new();
-    let n = &target.name;
-    for t in target.text {
-        add_tag(&mut xml, n, &t);
-    }
-    let s = vec_to_xml(target.value);
-    if s.len() > 0 {
-        add_tag(&mut xml, n, &s);
-    }
+    push_xml_vec(&mut xml, vec![target], Layout::new(options));
     xml
 }
+
+//element whose children are being rendered
+struct XmlFrame<'a> {
+    name: String,
+    children: std::vec::IntoIter<Target>,
+    rendered: String,
+    layout: Layout<'a>,
+}
+
+//depth first with a stack of open elements, each element is closed around its rendered children
+fn push_xml_vec(xml: &mut String, vec: Vec<Target>, layout: Layout) {
+    let mut stack = Vec::new();
+    let mut children = vec.into_iter();
+    loop {
+        let (out, iter, child_layout) = match stack.last_mut() {
+            Some(XmlFrame {
+                children,
+                rendered,
+                layout,
+                ..
+            }) => (rendered, children, layout.child()),
+            None => (&mut *xml, &mut children, layout),
+        };
+        match iter.next() {
+            Some(target) => {
+                for t in target.text {
+                    add_tag(out, child_layout, &target.name, &t);
+                }
+                stack.push(XmlFrame {
+                    name: target.name,
+                    children: target.value.into_iter(),
+                    rendered: String::new(),
+                    layout: child_layout,
+                });
+            }
+            None => {
+                let Some(frame) = stack.pop() else {
+                    return;
+                };
+                let out = match stack.last_mut() {
+                    Some(parent) => &mut parent.rendered,
+                    None => &mut *xml,
+                };
+                if !frame.rendered.is_empty() {
+                    add_tag_with_attribute(out, frame.layout, &frame.name, &[], &frame.rendered);
+                }
+            }
+        }
+    }
+}
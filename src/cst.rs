@@ -0,0 +1,509 @@
+use crate::writer::{push_key, push_text};
+use crate::{parse_chars, Contents, ParseError};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Result;
+
+///Kind of `Token`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    ///Number sign(#) at the start of a header line.
+    Hash,
+    ///Rest of a header line.
+    HeaderText,
+    ///Spaces before a continuation marker, or before an entry or on a blank line after a text.
+    Indent,
+    ///Offset digits, as written.
+    Offset,
+    Tilde,
+    ///Key chars without backslash.
+    Key,
+    ///Backslash and the char after it.
+    Escape,
+    Colon,
+    ///Text chars up to the line break.
+    Text,
+    Vertical,
+    Plus,
+    ///CR, LF or CRLF.
+    NewLine,
+    ///Line, or rest of a line, that the parser rejects.
+    Invalid,
+}
+
+///Piece of the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+}
+
+impl Token {
+    pub fn new(kind: TokenKind, text: &str) -> Self {
+        Token {
+            kind,
+            text: text.to_string(),
+        }
+    }
+}
+
+///Entry line with its continuation lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub tokens: Vec<Token>,
+}
+
+///Line or lines of the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    ///Header line at the top.
+    Header(Vec<Token>),
+    Entry(Entry),
+    ///Empty line, or line of spaces after a text.
+    Blank(Vec<Token>),
+    ///Line that the parser rejects, kept as is.
+    Invalid(Vec<Token>),
+}
+
+impl Node {
+    pub fn tokens(&self) -> &[Token] {
+        match self {
+            Node::Header(t) | Node::Blank(t) | Node::Invalid(t) => t,
+            Node::Entry(e) => &e.tokens,
+        }
+    }
+
+    fn tokens_mut(&mut self) -> &mut Vec<Token> {
+        match self {
+            Node::Header(t) | Node::Blank(t) | Node::Invalid(t) => t,
+            Node::Entry(e) => &mut e.tokens,
+        }
+    }
+}
+
+///Lossless syntax tree. Printing it with `Display` gives the source back byte for byte.
+///
+///```
+///use objnor::cst::Cst;
+///
+///let mut cst = Cst::parse("#h\r\n00~a:x\r\n  |y\r\n");
+///assert_eq!(cst.to_string(), "#h\r\n00~a:x\r\n  |y\r\n");
+///let e = cst.entries_mut().next().unwrap();
+///assert_eq!(e.texts(), vec!["x\r\ny"]);
+///e.set_key("b:c").unwrap();
+///assert_eq!(cst.to_string(), "#h\r\n00~b\\:c:x\r\n  |y\r\n");
+///```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cst {
+    pub nodes: Vec<Node>,
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for n in &self.nodes {
+            for t in n.tokens() {
+                f.write_str(&t.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//lines with their line break, the last one may have none
+fn lines(s: &str) -> Vec<(&str, &str)> {
+    let mut rst = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        match rest.find(['\r', '\n']) {
+            Some(i) => {
+                let n = if rest[i..].starts_with("\r\n") { 2 } else { 1 };
+                rst.push((&rest[..i], &rest[i..i + n]));
+                rest = &rest[i + n..];
+            }
+            None => {
+                rst.push((rest, ""));
+                rest = "";
+            }
+        }
+    }
+    rst
+}
+
+//key chars and escapes up to the colon, and the rest of the line from the colon
+fn key_tokens(s: &str) -> (Vec<Token>, &str) {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut iter = s.char_indices();
+    while let Some((i, c)) = iter.next() {
+        if c == ':' || c == '\\' {
+            if start < i {
+                tokens.push(Token::new(TokenKind::Key, &s[start..i]));
+            }
+            if c == ':' {
+                return (tokens, &s[i..]);
+            }
+            let end = iter.next().map_or(s.len(), |(j, c)| j + c.len_utf8());
+            tokens.push(Token::new(TokenKind::Escape, &s[i..end]));
+            start = end;
+        }
+    }
+    if start < s.len() {
+        tokens.push(Token::new(TokenKind::Key, &s[start..]));
+    }
+    (tokens, "")
+}
+
+//entry line, maybe after spaces
+fn entry_line(line: &str) -> Option<Vec<Token>> {
+    let rest = line.trim_start_matches(' ');
+    let mut tokens = Vec::new();
+    if rest.len() < line.len() {
+        tokens.push(Token::new(
+            TokenKind::Indent,
+            &line[..line.len() - rest.len()],
+        ));
+    }
+    let line = rest;
+    let digits = line.len()
+        - line
+            .trim_start_matches(|c: char| c.is_ascii_hexdigit())
+            .len();
+    if digits == 0 || !line[digits..].starts_with('~') {
+        return None;
+    }
+    tokens.push(Token::new(TokenKind::Offset, &line[..digits]));
+    tokens.push(Token::new(TokenKind::Tilde, "~"));
+    let (key, rest) = key_tokens(&line[digits + 1..]);
+    if key.is_empty() {
+        return None;
+    }
+    tokens.extend(key);
+    if let Some(text) = rest.strip_prefix(':') {
+        tokens.push(Token::new(TokenKind::Colon, ":"));
+        if !text.is_empty() {
+            tokens.push(Token::new(TokenKind::Text, text));
+        }
+    }
+    Some(tokens)
+}
+
+fn continuation_line(line: &str) -> Option<Vec<Token>> {
+    let rest = line.trim_start_matches(' ');
+    let kind = match rest.chars().next()? {
+        '|' => TokenKind::Vertical,
+        '+' => TokenKind::Plus,
+        _ => return None,
+    };
+    let mut tokens = Vec::new();
+    if rest.len() < line.len() {
+        tokens.push(Token::new(
+            TokenKind::Indent,
+            &line[..line.len() - rest.len()],
+        ));
+    }
+    tokens.push(Token::new(kind, &rest[..1]));
+    if rest.len() > 1 {
+        tokens.push(Token::new(TokenKind::Text, &rest[1..]));
+    }
+    Some(tokens)
+}
+
+//rows of the chars that the parser rejects
+#[derive(Default)]
+struct ErrorRows(HashSet<usize>);
+
+impl Contents for ErrorRows {
+    type Item = HashSet<usize>;
+
+    fn get(self) -> Self::Item {
+        self.0
+    }
+
+    fn pre_key(&mut self, _offset: usize) {}
+
+    fn key(&mut self, _c: char) {}
+
+    fn post_key(&mut self) {}
+
+    fn pre_text(&mut self) {}
+
+    fn text(&mut self, _c: char) {}
+
+    fn text_array(&mut self) {}
+
+    fn post_text(&mut self) {}
+
+    fn error(&mut self, error: ParseError) {
+        self.0.insert(error.row);
+    }
+}
+
+impl Cst {
+    ///Parse source, never fails. Lines where the parser reports an error become `Node::Invalid`.
+    pub fn parse(s: &str) -> Self {
+        Self::from_lines(s, &parse_chars(s.chars(), ErrorRows::default()))
+    }
+
+    //the parser counts rows like `lines` splits them
+    fn from_lines(s: &str, errors: &HashSet<usize>) -> Self {
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut header = true;
+        for (row, (line, new_line)) in lines(s).into_iter().enumerate() {
+            if errors.contains(&(row + 1)) {
+                //a blank line in the header is rejected, the header goes on
+                header &= line.is_empty();
+                nodes.push(Node::Invalid(vec![Token::new(TokenKind::Invalid, line)]));
+            } else if header && line.starts_with('#') {
+                let mut t = vec![Token::new(TokenKind::Hash, "#")];
+                if line.len() > 1 {
+                    t.push(Token::new(TokenKind::HeaderText, &line[1..]));
+                }
+                nodes.push(Node::Header(t));
+            } else {
+                header = false;
+                let continued = match (nodes.last_mut(), continuation_line(line)) {
+                    (Some(Node::Entry(e)), Some(t)) if e.has_text() && e.is_closed() => {
+                        e.tokens.extend(t);
+                        true
+                    }
+                    _ => false,
+                };
+                if !continued {
+                    nodes.push(if line.trim_start_matches(' ').is_empty() {
+                        let mut t = Vec::new();
+                        if !line.is_empty() {
+                            t.push(Token::new(TokenKind::Indent, line));
+                        }
+                        Node::Blank(t)
+                    } else if let Some(t) = entry_line(line) {
+                        Node::Entry(Entry { tokens: t })
+                    } else {
+                        Node::Invalid(vec![Token::new(TokenKind::Invalid, line)])
+                    });
+                }
+            }
+            if let Some(n) = nodes.last_mut().filter(|_| !new_line.is_empty()) {
+                n.tokens_mut()
+                    .push(Token::new(TokenKind::NewLine, new_line));
+            }
+        }
+        Cst { nodes }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.nodes.iter().filter_map(|n| match n {
+            Node::Entry(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut Entry> {
+        self.nodes.iter_mut().filter_map(|n| match n {
+            Node::Entry(e) => Some(e),
+            _ => None,
+        })
+    }
+}
+
+impl Entry {
+    ///New entry line ending with LF, and continuation lines for line breaks and more texts.
+    ///
+    ///Fails like `to_text`, if the key is empty or contains a line break, or if a key or text contains `'\0'`.
+    pub fn new(offset: usize, key: &str, texts: &[String]) -> Result<Self> {
+        let mut s = format!("{:x}~", offset);
+        push_key(&mut s, key)?;
+        push_texts(&mut s, texts, "\n")?;
+        s.push('\n');
+        Ok(Self::from_source(&s))
+    }
+
+    //tokens of one entry written by this module, its offset is not checked
+    fn from_source(s: &str) -> Self {
+        match Cst::from_lines(s, &HashSet::new()).nodes.pop() {
+            Some(Node::Entry(e)) => e,
+            n => unreachable!("{:?}", n),
+        }
+    }
+
+    fn has_text(&self) -> bool {
+        self.tokens.iter().any(|t| t.kind == TokenKind::Colon)
+    }
+
+    //ends with a line break
+    fn is_closed(&self) -> bool {
+        self.tokens
+            .last()
+            .is_some_and(|t| t.kind == TokenKind::NewLine)
+    }
+
+    pub fn offset(&self) -> Option<usize> {
+        let t = self.tokens.iter().find(|t| t.kind == TokenKind::Offset)?;
+        usize::from_str_radix(&t.text, 16).ok()
+    }
+
+    ///Key without escapes.
+    pub fn key(&self) -> String {
+        let mut s = String::new();
+        for t in self.key_range().map(|i| &self.tokens[i]) {
+            match t.kind {
+                TokenKind::Escape => s.push_str(&t.text[1..]),
+                _ => s.push_str(&t.text),
+            }
+        }
+        s
+    }
+
+    ///Texts as the parser reads them, empty if the entry has no colon(:).
+    pub fn texts(&self) -> Vec<String> {
+        let mut rst = Vec::new();
+        if !self.has_text() {
+            return rst;
+        }
+        let mut text = String::new();
+        let mut new_line = "";
+        for t in &self.tokens[self.key_range().end..] {
+            match t.kind {
+                TokenKind::Text => text.push_str(&t.text),
+                TokenKind::NewLine => new_line = &t.text,
+                TokenKind::Vertical => text.push_str(new_line),
+                TokenKind::Plus => rst.push(std::mem::take(&mut text)),
+                _ => {}
+            }
+        }
+        rst.push(text);
+        rst
+    }
+
+    ///Set offset, written in lowercase hexadecimal.
+    pub fn set_offset(&mut self, offset: usize) {
+        if let Some(t) = self.tokens.iter_mut().find(|t| t.kind == TokenKind::Offset) {
+            t.text = format!("{:x}", offset);
+        }
+    }
+
+    ///Set key, escaping colon(:) and backslash. Other tokens are kept.
+    pub fn set_key(&mut self, key: &str) -> Result<()> {
+        let mut s = String::new();
+        push_key(&mut s, key)?;
+        let range = self.key_range();
+        self.tokens.splice(range, key_tokens(&s).0);
+        Ok(())
+    }
+
+    ///Set texts, with continuation lines for line breaks and more texts.
+    ///No texts removes the colon(:). The offset, key and last line break are kept.
+    pub fn set_texts(&mut self, texts: &[String]) -> Result<()> {
+        let new_line = match self.tokens.last() {
+            Some(t) if t.kind == TokenKind::NewLine => Some(t.text.clone()),
+            _ => None,
+        };
+        let mut s = String::from("0~k");
+        push_texts(&mut s, texts, new_line.as_deref().unwrap_or("\n"))?;
+        s.push_str(new_line.as_deref().unwrap_or(""));
+        let e = Self::from_source(&s);
+        let start = e.key_range().end;
+        let end = self.key_range().end;
+        self.tokens.splice(end.., e.tokens[start..].iter().cloned());
+        Ok(())
+    }
+
+    //indexes of key and escape tokens
+    fn key_range(&self) -> std::ops::Range<usize> {
+        let start = self
+            .tokens
+            .iter()
+            .position(|t| t.kind == TokenKind::Tilde)
+            .map_or(0, |i| i + 1);
+        let len = self.tokens[start..]
+            .iter()
+            .take_while(|t| matches!(t.kind, TokenKind::Key | TokenKind::Escape))
+            .count();
+        start..start + len
+    }
+}
+
+//colon and texts, plus(+) lines after new_line for more texts
+fn push_texts(s: &mut String, texts: &[String], new_line: &str) -> Result<()> {
+    for (i, t) in texts.iter().enumerate() {
+        if i == 0 {
+            s.push(':');
+        } else {
+            s.push_str(new_line);
+            s.push('+');
+        }
+        push_text(s, t)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chars_to_document, try_chars_to_target};
+
+    #[test]
+    fn test_lossless() {
+        let kinds = |s: &str| -> Vec<&str> {
+            let cst = Cst::parse(s);
+            assert_eq!(cst.to_string(), s);
+            cst.nodes
+                .iter()
+                .map(|n| match n {
+                    Node::Header(_) => "header",
+                    Node::Entry(_) => "entry",
+                    Node::Blank(_) => "blank",
+                    Node::Invalid(_) => "invalid",
+                })
+                .collect()
+        };
+        let s = "#h1\n##h2\r\n00~k\\:ey\\\\:x\r\n  |y\r+z\n\n1~b\n  0~c:\n|\n!bad\n0~\n0~d:1\n+2";
+        assert_eq!(
+            kinds(s),
+            vec![
+                "header", "header", "entry", "blank", "entry", "invalid", "invalid", "invalid",
+                "invalid", "entry"
+            ]
+        );
+        //invalid lines are the lines where the parser reports errors
+        assert_eq!(
+            kinds("\n#h\n0~a:x\n  \n0~b:y\n  0~c\n0A~d\n2~e:f\n1~g\n  \n"),
+            vec![
+                "invalid", "header", "entry", "blank", "entry", "entry", "invalid", "invalid",
+                "entry", "invalid"
+            ]
+        );
+        let cst = Cst::parse(s);
+        let e: Vec<&Entry> = cst.entries().collect();
+        assert_eq!(e[0].offset(), Some(0));
+        assert_eq!(e[0].key(), "k:ey\\");
+        assert_eq!(e[0].texts(), vec!["x\r\ny", "z"]);
+        assert_eq!(e[1].texts(), Vec::<String>::new());
+        assert_eq!(e[2].texts(), vec!["1", "2"]);
+
+        let s = "0~a:x\r\n|y\r\n1~B:z\r\n";
+        let mut cst = Cst::parse(s);
+        let texts: Vec<Vec<String>> = cst.entries().map(Entry::texts).collect();
+        let t = try_chars_to_target(s.chars()).unwrap();
+        assert_eq!(texts[0], t[0].text);
+        assert_eq!(texts[1], t[0].value[0].text);
+
+        {
+            let mut e = cst.entries_mut();
+            let a = e.next().unwrap();
+            a.set_texts(&["1\n2".to_string(), "3".to_string()]).unwrap();
+            let b = e.next().unwrap();
+            b.set_offset(0);
+            b.set_key("c\\").unwrap();
+            b.set_texts(&[]).unwrap();
+        }
+        cst.nodes
+            .push(Node::Entry(Entry::new(1, "d", &["4".to_string()]).unwrap()));
+        let s = cst.to_string();
+        assert_eq!(s, "0~a:1\n|2\r\n+3\r\n0~c\\\\\r\n1~d:4\n");
+        let d = chars_to_document(s.chars()).unwrap();
+        assert_eq!(d.targets[0].text, vec!["1\n2", "3"]);
+        assert_eq!(d.targets[1].name, "c\\");
+        assert_eq!(d.targets[1].value[0].text, vec!["4"]);
+        assert!(Entry::new(0, "", &[]).is_err());
+    }
+}
@@ -0,0 +1,585 @@
+use crate::{chars_to_document_with, ParseOptions, Position, Target};
+use serde::de::value::StrDeserializer;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use std::fmt;
+
+//levels of nesting, deserializing takes much more stack per level than dropping
+const MAX_DEPTH: usize = 128;
+
+///Error when convert text format to a `Deserialize` type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    pub message: String,
+    ///Row and column of the parser error, or of the key or text the error is about.
+    pub position: Option<(usize, usize)>,
+}
+
+impl Error {
+    fn new(message: String) -> Self {
+        Error {
+            message,
+            position: None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((row, column)) => write!(f, "{} at {}:{}", self.message, row, column),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::new(msg.to_string())
+    }
+}
+
+///Parse text format to any `Deserialize` type.
+///
+///Keys map to struct fields or map keys, offsets to nested structs, plus(+) arrays and
+///repeated keys to sequences, and text to strings, numbers, bools or unit enum variants.
+///Input nested more than 128 levels deep is an error, as deserializing is recursive.
+pub fn from_str<T: DeserializeOwned>(s: &str) -> Result<T, Error> {
+    let mut options = ParseOptions::new();
+    options.ordered = true;
+    options.spans = true;
+    options.limits.max_depth = Some(MAX_DEPTH);
+    let document = chars_to_document_with(s.chars(), &options).map_err(|e| Error {
+        message: e[0].kind.to_string(),
+        position: Some((e[0].row, e[0].column)),
+    })?;
+    let mut root = Target::new(String::new());
+    root.value = document.targets;
+    T::deserialize(ValueDeserializer {
+        value: Value::Group(vec![&root]),
+        path: String::new(),
+    })
+}
+
+//all occurrences of a key, or one text in an array with its position
+enum Value<'a> {
+    Group(Vec<&'a Target>),
+    Text(&'a str, Option<Position>),
+}
+
+struct ValueDeserializer<'a> {
+    value: Value<'a>,
+    path: String,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    fn error(&self, msg: impl fmt::Display) -> Error {
+        let mut e = if self.path.is_empty() {
+            Error::new(msg.to_string())
+        } else {
+            Error::new(format!("{}: {}", self.path, msg))
+        };
+        e.position = self.position().map(|p| (p.row, p.column));
+        e
+    }
+
+    //first text, or first key
+    fn position(&self) -> Option<Position> {
+        match &self.value {
+            Value::Group(g) => {
+                let spans = g.first()?.spans.as_ref()?;
+                spans.text.first().or(spans.key.first()).map(|s| s.start)
+            }
+            Value::Text(_, p) => *p,
+        }
+    }
+
+    fn single(&self) -> Result<&'a Target, Error> {
+        match &self.value {
+            Value::Group(g) if g.len() == 1 => Ok(g[0]),
+            Value::Group(_) => Err(self.error("key occurs more than once")),
+            Value::Text(..) => Err(self.error("expected key, found text")),
+        }
+    }
+
+    fn text(&self) -> Result<&'a str, Error> {
+        match self.value {
+            Value::Text(s, _) => Ok(s),
+            Value::Group(_) => {
+                let t = self.single()?;
+                if !t.value.is_empty() {
+                    return Err(self.error("expected text, found children"));
+                }
+                match t.text.len() {
+                    0 => Ok(""),
+                    1 => Ok(&t.text[0]),
+                    _ => Err(self.error("expected text, found array")),
+                }
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.value {
+            Value::Group(g) => g.len() == 1 && g[0].text.is_empty() && g[0].value.is_empty(),
+            Value::Text(..) => false,
+        }
+    }
+
+    fn parse<T>(&self) -> Result<T, Error>
+    where
+        T: std::str::FromStr,
+        T::Err: fmt::Display,
+    {
+        self.text()?
+            .trim()
+            .parse()
+            .map_err(|e| self.error(format!("{}", e)))
+    }
+
+    fn child_path(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.path, name)
+        }
+    }
+
+    fn map(&self) -> Result<Map<'a>, Error> {
+        let t = self.single()?;
+        if !t.text.is_empty() {
+            return Err(self.error("expected children, found text"));
+        }
+        let mut entries: Vec<(&'a str, Vec<&'a Target>)> = Vec::new();
+        for c in &t.value {
+            if let Some(e) = entries.iter_mut().find(|e| e.0 == c.name) {
+                e.1.push(c);
+            } else {
+                entries.push((&c.name, vec![c]));
+            }
+        }
+        Ok(Map {
+            entries: entries.into_iter(),
+            value: None,
+            path: self.path.clone(),
+        })
+    }
+
+    fn seq(&self) -> Result<Seq<'a>, Error> {
+        let mut values = Vec::new();
+        match &self.value {
+            Value::Group(g) => {
+                for t in g {
+                    if t.value.is_empty() {
+                        let spans = t.spans.as_ref().map(|s| &s.text[..]).unwrap_or(&[]);
+                        values.extend(
+                            t.text
+                                .iter()
+                                .enumerate()
+                                .map(|(i, s)| Value::Text(s, spans.get(i).map(|s| s.start))),
+                        );
+                    } else {
+                        values.push(Value::Group(vec![t]));
+                    }
+                }
+            }
+            Value::Text(..) => return Err(self.error("expected array, found text")),
+        }
+        Ok(Seq {
+            values: values.into_iter(),
+            index: 0,
+            path: self.path.clone(),
+        })
+    }
+}
+
+macro_rules! deserialize_parse {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match &self.value {
+            Value::Text(s, _) => visitor.visit_str(s),
+            Value::Group(g) if g.len() > 1 => self.deserialize_seq(visitor),
+            Value::Group(g) => {
+                let t = g[0];
+                if !t.value.is_empty() {
+                    self.deserialize_map(visitor)
+                } else if t.text.len() > 1 {
+                    self.deserialize_seq(visitor)
+                } else if let Some(s) = t.text.first() {
+                    visitor.visit_str(s)
+                } else {
+                    visitor.visit_unit()
+                }
+            }
+        }
+    }
+
+    deserialize_parse! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let s = self.text()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(self.error(format!("expected a char, found {:?}", s))),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str(self.text()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bytes(self.text()?.as_bytes())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    //a key without text or children is none
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(self.seq()?)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(self.map()?)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_map(visitor)
+    }
+
+    //unit variant as text, other variants as the only child
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if let Value::Group(_) = self.value {
+            let t = self.single()?;
+            if !t.value.is_empty() {
+                if t.value.len() > 1 || !t.text.is_empty() {
+                    return Err(self.error("expected one child as enum variant"));
+                }
+                let c = &t.value[0];
+                return visitor.visit_enum(Variant {
+                    name: &c.name,
+                    value: Some(ValueDeserializer {
+                        value: Value::Group(vec![c]),
+                        path: self.child_path(&c.name),
+                    }),
+                });
+            }
+        }
+        visitor.visit_enum(Variant {
+            name: self.text()?,
+            value: None,
+        })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+struct Map<'a> {
+    entries: std::vec::IntoIter<(&'a str, Vec<&'a Target>)>,
+    value: Option<(&'a str, Vec<&'a Target>)>,
+    path: String,
+}
+
+impl<'de, 'a> MapAccess<'de> for Map<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some(e) => {
+                let name: StrDeserializer<Error> = e.0.into_deserializer();
+                let key = seed.deserialize(name);
+                self.value = Some(e);
+                key.map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (name, group) = self
+            .value
+            .take()
+            .ok_or_else(|| Error::new("value is missing".to_string()))?;
+        let path = if self.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.path, name)
+        };
+        seed.deserialize(ValueDeserializer {
+            value: Value::Group(group),
+            path,
+        })
+    }
+}
+
+struct Seq<'a> {
+    values: std::vec::IntoIter<Value<'a>>,
+    index: usize,
+    path: String,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Seq<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.values.next() {
+            Some(value) => {
+                let path = format!("{}[{}]", self.path, self.index);
+                self.index += 1;
+                seed.deserialize(ValueDeserializer { value, path })
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+struct Variant<'a> {
+    name: &'a str,
+    value: Option<ValueDeserializer<'a>>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for Variant<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let name: StrDeserializer<Error> = self.name.into_deserializer();
+        Ok((seed.deserialize(name)?, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for Variant<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.value {
+            Some(d) => seed.deserialize(d),
+            None => Err(Error::new(format!("{}: expected children", self.name))),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Some(d) => de::Deserializer::deserialize_seq(d, visitor),
+            None => Err(Error::new(format!("{}: expected children", self.name))),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.value {
+            Some(d) => de::Deserializer::deserialize_map(d, visitor),
+            None => Err(Error::new(format!("{}: expected children", self.name))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseErrorKind;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Mode {
+        Fast,
+        Limit { rate: u32 },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Server {
+        host: String,
+        port: u16,
+        tls: Option<bool>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        name: String,
+        debug: bool,
+        ratio: f64,
+        tags: Vec<String>,
+        servers: Vec<Server>,
+        mode: Mode,
+        other: Mode,
+        env: HashMap<String, String>,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn test_from_str() {
+        let s = "0~name:a
+|b
+0~debug:true
+0~ratio: 0.5
+0~tags:x
++y
+0~servers
+1~host:h1
+1~port:80
+1~tls:false
+0~servers
+1~host:h2
+1~port:81
+0~mode:Fast
+0~other
+1~Limit
+2~rate:3
+0~env
+1~A:1
+1~B:2
+";
+        let c: Config = from_str(s).unwrap();
+        assert_eq!(c.name, "a\nb");
+        assert!(c.debug);
+        assert_eq!(c.ratio, 0.5);
+        assert_eq!(c.tags, vec!["x", "y"]);
+        assert_eq!(c.servers.len(), 2);
+        assert_eq!(c.servers[0].tls, Some(false));
+        assert_eq!(c.servers[1].port, 81);
+        assert_eq!(c.servers[1].tls, None);
+        assert_eq!(c.mode, Mode::Fast);
+        assert_eq!(c.other, Mode::Limit { rate: 3 });
+        assert_eq!(c.env["B"], "2");
+        assert_eq!(c.note, None);
+
+        let e = from_str::<Server>("0~host:h\n0~port:x\n").unwrap_err();
+        assert_eq!(e.message, "port: invalid digit found in string");
+        assert_eq!(e.to_string(), "port: invalid digit found in string at 2:8");
+        let e = from_str::<Server>("0~host:h\n2~port:1\n").unwrap_err();
+        assert_eq!(e.position, Some((2, 2)));
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Node {
+        a: Option<Box<Node>>,
+        v: Option<String>,
+    }
+
+    #[test]
+    fn test_deep() {
+        let mut s = String::new();
+        for i in 0..127 {
+            s.push_str(&format!("{:x}~a\n", i));
+        }
+        let mut node = from_str::<Node>(&format!("{}7f~v:x\n", s)).unwrap();
+        while let Some(a) = node.a {
+            node = *a;
+        }
+        assert_eq!(node.v.as_deref(), Some("x"));
+        let e = from_str::<Node>(&format!("{}7f~a\n80~v:x\n", s)).unwrap_err();
+        assert_eq!(e.message, ParseErrorKind::TooDeep.to_string());
+    }
+}
@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+///The reason why the parser rejected a char.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    ///A line break where a header or an offset is expected.
+    UnexpectedNewLine,
+    ///Offset is not a hexadecimal number.
+    InvalidOffset,
+    ///Offset is greater than the previous offset plus one.
+    OffsetJump,
+    ///Line break between offset and tilde(~).
+    MissingTilde,
+    ///Colon(:) or line break right after tilde(~).
+    EmptyKey,
+    ///Continuation line does not start with vertical(|) or plus(+).
+    InvalidContinuation,
+    ///NUL char in input, it is skipped.
+    NulChar,
+    ///Bytes that are not UTF-8, they are read as U+FFFD.
+    InvalidUtf8,
+    ///Offset plus one is greater than `Limits.max_depth`.
+    TooDeep,
+    ///Key has more chars than `Limits.max_key_len`.
+    KeyTooLong,
+    ///Text or header line has more chars than `Limits.max_text_len`.
+    TextTooLong,
+    ///Entry has more texts than `Limits.max_array_items`.
+    TooManyItems,
+    ///Document has more entries than `Limits.max_nodes`.
+    TooManyNodes,
+}
+
+impl ParseErrorKind {
+    fn description(&self) -> &'static str {
+        match self {
+            ParseErrorKind::UnexpectedNewLine => "unexpected line break",
+            ParseErrorKind::InvalidOffset => "offset is not a hexadecimal number",
+            ParseErrorKind::OffsetJump => "offset jumped by more than one",
+            ParseErrorKind::MissingTilde => "missing tilde after offset",
+            ParseErrorKind::EmptyKey => "key is empty",
+            ParseErrorKind::InvalidContinuation => "invalid continuation marker",
+            ParseErrorKind::NulChar => "NUL char",
+            ParseErrorKind::InvalidUtf8 => "invalid UTF-8",
+            ParseErrorKind::TooDeep => "nesting is too deep",
+            ParseErrorKind::KeyTooLong => "key is too long",
+            ParseErrorKind::TextTooLong => "text is too long",
+            ParseErrorKind::TooManyItems => "too many array items",
+            ParseErrorKind::TooManyNodes => "too many entries",
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+///Error found when parse text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    ///Row number, starting at 1.
+    pub row: usize,
+    ///Column number in chars, starting at 1.
+    pub column: usize,
+    ///The offending char, `None` at the end of input.
+    pub char: Option<char>,
+    ///Name of the parser state that rejected the char, e.g. `accept_offset`.
+    pub state: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.kind, self.row, self.column)?;
+        match self.char {
+            Some(c) => write!(f, ", found {:?}", c)?,
+            None => write!(f, ", found end of input")?,
+        }
+        write!(f, " ({})", self.state)
+    }
+}
+
+impl Error for ParseError {}
+
+///Error when parse from `std::io::Read`.
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    ///All errors found, with invalid UTF-8 as `ParseErrorKind::InvalidUtf8`.
+    Parse(Vec<ParseError>),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => e.fmt(f),
+            ReadError::Parse(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("\n")?;
+                    }
+                    e.fmt(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            ReadError::Parse(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
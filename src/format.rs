@@ -0,0 +1,78 @@
+use crate::{chars_to_document_with, vec_to_text, FmtOptions, ParseError, ParseOptions, Target};
+
+///Format text format in canonical style, or all errors found.
+///
+///Offsets become minimal lowercase hexadecimal, line breaks become LF, blank lines and spaces
+///before vertical(|) and plus(+) are removed, and trailing whitespace of header and text lines is removed.
+///A line break in a text is written with vertical(|), and every text after the first in an array with plus(+).
+pub fn fmt(s: &str) -> Result<String, Vec<ParseError>> {
+    fmt_with(s, &FmtOptions::default())
+}
+
+///Format text format in canonical style with options, or all errors found.
+pub fn fmt_with(s: &str, options: &FmtOptions) -> Result<String, Vec<ParseError>> {
+    let parse_options = ParseOptions {
+        ordered: true,
+        ..options.parse.clone()
+    };
+    let mut d = chars_to_document_with(s.chars(), &parse_options)?;
+    let mut rst = String::new();
+    for h in &d.header {
+        rst.push('#');
+        rst.push_str(normalize(h, options));
+        rst.push('\n');
+    }
+    normalize_targets(&mut d.targets, options);
+    //keys and texts come from the parser, so they can be written
+    rst.push_str(&vec_to_text(&d.targets).expect("parsed document is valid"));
+    Ok(rst)
+}
+
+fn normalize<'a>(s: &'a str, options: &FmtOptions) -> &'a str {
+    if options.trim_trailing_whitespace {
+        s.trim_end_matches([' ', '\t'])
+    } else {
+        s
+    }
+}
+
+fn normalize_targets(vec: &mut [Target], options: &FmtOptions) {
+    for t in vec.iter_mut() {
+        for text in &mut t.text {
+            let lines: Vec<&str> = text
+                .split("\r\n")
+                .flat_map(|l| l.split(['\r', '\n']))
+                .map(|l| normalize(l, options))
+                .collect();
+            *text = lines.join("\n");
+        }
+        t.text.retain(|s| !s.is_empty());
+        normalize_targets(&mut t.value, options);
+    }
+    if options.sort_keys {
+        vec.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fmt() {
+        let s = "#h #1 \r\n0000~b :x \r\n  |y\t\r\r\n\n0~a:1\r\n + 2\n1~c\n1~b:\n0~b:z";
+        assert_eq!(
+            fmt(s).unwrap(),
+            "#h #1\n0~b :x\n|y\n0~a:1\n+ 2\n1~c\n1~b\n0~b:z\n"
+        );
+        let mut options = FmtOptions::new();
+        options.sort_keys = true;
+        assert_eq!(
+            fmt_with(s, &options).unwrap(),
+            "#h #1\n0~a:1\n+ 2\n1~b\n1~c\n0~b:z\n0~b :x\n|y\n"
+        );
+        assert!(fmt("1~a").is_err());
+        options.parse.strict = true;
+        assert_eq!(fmt_with("0~a\n2~b\n0~:c", &options).unwrap_err().len(), 1);
+    }
+}
@@ -7,7 +7,11 @@ impl Header {
         Header { v: Vec::new() }
     }
 
-    pub(crate) fn accept(&mut self, str: String) {
-        self.v.push(str);
+    pub(crate) fn accept(&mut self, str: &str) {
+        self.v.push(str.to_string());
+    }
+
+    pub(crate) fn get(self) -> Vec<String> {
+        self.v
     }
 }
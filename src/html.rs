@@ -1,5 +1,9 @@
-use crate::xml::{add_attribute, add_tag, add_tag_with_attribute};
-use crate::Target;
+use crate::options::MAX_DEPTH;
+use crate::xml::{
+    add_attribute, add_start_tag, add_tag, add_tag_with_attribute, add_tag_with_content,
+    attribute_name, push_text, Layout,
+};
+use crate::{OutputOptions, Target};
 use std::collections::{HashMap, HashSet};
 
 const GLOBALS: [&str; 25] = [
@@ -151,72 +155,865 @@ const ELEMENT_ATTRIBUTES: [(&str, &str); 47] = [
     ("video", "src,crossorigin,poster,preload,autoplay,playsinline,loop,muted,controls,width,height"),
 ];
 
-//special case
-fn is_element(element: &str, s: &str) -> bool {
-    if element == "head" && s == "title" {
-        return true;
+//trusted markup, its text is not escaped
+const RAW: &str = "#raw";
+
+//text between child elements, its text is escaped
+const TEXT: &str = "#text";
+
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+//escapable raw text, entities are decoded but tags are not recognized
+const RCDATA_ELEMENTS: [&str; 2] = ["textarea", "title"];
+
+//elements without content and end tag
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+fn is_void_element(element: &str) -> bool {
+    VOID_ELEMENTS.contains(&element.to_lowercase().as_str())
+}
+
+fn is_raw_text_element(element: &str) -> bool {
+    RAW_TEXT_ELEMENTS.contains(&element.to_lowercase().as_str())
+}
+
+//raw text element content is not escaped, but "</" before its own name is written as "<\/"
+fn push_raw_text(s: &mut String, element: &str, text: &str) {
+    let mut rest = text;
+    while let Some(i) = rest.find("</") {
+        let after = &rest[i + 2..];
+        s.push_str(&rest[..i]);
+        if after.len() >= element.len()
+            && after.is_char_boundary(element.len())
+            && after[..element.len()].eq_ignore_ascii_case(element)
+        {
+            s.push_str("<\\/");
+        } else {
+            s.push_str("</");
+        }
+        rest = after;
+    }
+    s.push_str(rest);
+}
+
+//moved into head by DocumentOptions.lift_head
+const HEAD_ELEMENTS: [&str; 3] = ["title", "link", "meta"];
+
+///Options for `Converter::convert_document`.
+#[derive(Clone, Debug)]
+pub struct DocumentOptions {
+    ///Charset of the `meta charset` put first in head, when head has none. `None` puts nothing.
+    pub charset: Option<String>,
+    ///Move top level `title`, `link` and `meta` into head.
+    pub lift_head: bool,
+    pub output: OutputOptions,
+}
+
+impl Default for DocumentOptions {
+    fn default() -> Self {
+        DocumentOptions {
+            charset: Some("utf-8".to_string()),
+            lift_head: false,
+            output: OutputOptions::default(),
+        }
+    }
+}
+
+impl DocumentOptions {
+    pub fn new() -> Self {
+        Self::default()
     }
-    false
 }
 
 ///Convert `Target` to HTML String.
+///
+///Text and attribute values are escaped. A child named `#raw` is trusted markup, its text is written as is.
+///A child named `#text` is text between the other children, for mixed content.
+///Text of `script` and `style` is not escaped, but cannot close the element early.
+///Void elements like `img` get no end tag, and an attribute without text is a boolean attribute.
+///Text and children of a void element are written after its tag.
+///
+///A child is an attribute when its name is a global attribute, starts with an attribute prefix
+///or is registered for the element. The tables can be changed with the builder methods.
+///Element and attribute names are matched case insensitively, and keep their case in the output.
+///
+///```
+///use objnor::html::Converter;
+///
+///let c = Converter::new()
+///    .add_global("role")
+///    .add_prefix("aria-")
+///    .add_element_attribute("svg", "viewBox");
+///```
+#[derive(Clone, Debug)]
 pub struct Converter {
-    globals: HashSet<&'static str>,
-    event_handler: HashSet<&'static str>,
-    element_attributes: HashMap<&'static str, HashSet<&'static str>>,
+    globals: HashSet<String>,
+    prefixes: Vec<String>,
+    element_attributes: HashMap<String, HashSet<String>>,
+    //(element, child) pairs where the child is an element, e.g. title in head
+    exceptions: HashSet<(String, String)>,
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Converter {
     pub fn new() -> Self {
         let mut element_attributes = HashMap::new();
         for e in ELEMENT_ATTRIBUTES {
-            let attributes: Vec<&str> = e.1.split(',').collect();
-            element_attributes.insert(e.0, HashSet::from_iter(attributes));
+            let attributes = e.1.split(',').map(|a| a.trim().to_lowercase()).collect();
+            element_attributes.insert(e.0.to_string(), attributes);
         }
         Converter {
-            globals: HashSet::from(GLOBALS),
-            event_handler: HashSet::from(EVENT_HANDLER),
+            globals: GLOBALS
+                .iter()
+                .chain(EVENT_HANDLER.iter())
+                .map(|a| a.to_string())
+                .collect(),
+            prefixes: vec![DATA.to_string()],
             element_attributes,
+            exceptions: HashSet::from([("head".to_string(), "title".to_string())]),
+        }
+    }
+
+    ///Add global attribute, an attribute on every element.
+    pub fn add_global(mut self, attribute: &str) -> Self {
+        self.globals.insert(attribute.to_lowercase());
+        self
+    }
+
+    ///Remove global attribute or event handler.
+    pub fn remove_global(mut self, attribute: &str) -> Self {
+        self.globals.remove(&attribute.to_lowercase());
+        self
+    }
+
+    ///Add attribute prefix like `aria-`, a name starting with it is an attribute on every element.
+    pub fn add_prefix(mut self, prefix: &str) -> Self {
+        let prefix = prefix.to_lowercase();
+        if !self.prefixes.contains(&prefix) {
+            self.prefixes.push(prefix);
+        }
+        self
+    }
+
+    ///Remove attribute prefix, `data-` is there by default.
+    pub fn remove_prefix(mut self, prefix: &str) -> Self {
+        let prefix = prefix.to_lowercase();
+        self.prefixes.retain(|p| *p != prefix);
+        self
+    }
+
+    ///Add attribute on the element.
+    pub fn add_element_attribute(mut self, element: &str, attribute: &str) -> Self {
+        self.element_attributes
+            .entry(element.to_lowercase())
+            .or_default()
+            .insert(attribute.to_lowercase());
+        self
+    }
+
+    ///Remove attribute on the element.
+    pub fn remove_element_attribute(mut self, element: &str, attribute: &str) -> Self {
+        if let Some(attributes) = self.element_attributes.get_mut(&element.to_lowercase()) {
+            attributes.remove(&attribute.to_lowercase());
         }
+        self
+    }
+
+    ///Child with the name under the element is an element, even if it is a global or prefixed attribute.
+    ///`title` under `head` is there by default.
+    pub fn add_exception(mut self, element: &str, child: &str) -> Self {
+        self.exceptions
+            .insert((element.to_lowercase(), child.to_lowercase()));
+        self
+    }
+
+    ///Remove exception.
+    pub fn remove_exception(mut self, element: &str, child: &str) -> Self {
+        self.exceptions
+            .remove(&(element.to_lowercase(), child.to_lowercase()));
+        self
     }
 
     ///Convert `Target` to HTML String.
     pub fn convert(&self, target: Target) -> String {
+        self.convert_with(target, &OutputOptions::default())
+    }
+
+    ///Convert `Target` to HTML String with output options, e.g. indented.
+    pub fn convert_with(&self, target: Target, options: &OutputOptions) -> String {
         let mut rst = String::new();
-        self.convert_target(&mut rst, target);
+        self.convert_target(&mut rst, target, Layout::new(options));
+        rst
+    }
+
+    ///Convert `Vec<Target>` to HTML document with doctype.
+    ///
+    ///Children of `html`, `head` and `body` targets go to the elements of the same name,
+    ///the other targets go to body. Text of `html`, `head` and `body` and other top level text is body text.
+    pub fn convert_document(&self, targets: Vec<Target>, options: &DocumentOptions) -> String {
+        let mut html = Target::new("html".to_string());
+        let mut head = Target::new("head".to_string());
+        let mut body = Target::new("body".to_string());
+        let mut route = |t: Target| {
+            let name = t.name.to_lowercase();
+            if name == "head" || name == "body" {
+                for text in t.text {
+                    let mut c = Target::new(TEXT.to_string());
+                    c.text.push(text);
+                    body.value.push(c);
+                }
+                if name == "head" {
+                    head.value.extend(t.value);
+                } else {
+                    body.value.extend(t.value);
+                }
+            } else if options.lift_head && HEAD_ELEMENTS.contains(&name.as_str()) {
+                head.value.push(t);
+            } else {
+                body.value.push(t);
+            }
+        };
+        for t in targets {
+            if t.name.eq_ignore_ascii_case("html") {
+                let mut text = Target::new("body".to_string());
+                text.text = t.text;
+                route(text);
+                for c in t.value {
+                    if self.is_attribute("html", &c.name) {
+                        html.value.push(c);
+                    } else {
+                        route(c);
+                    }
+                }
+            } else {
+                route(t);
+            }
+        }
+        if let Some(charset) = &options.charset {
+            let has_charset = head.value.iter().any(|t| {
+                t.name.eq_ignore_ascii_case("meta")
+                    && t.value
+                        .iter()
+                        .any(|a| a.name.eq_ignore_ascii_case("charset"))
+            });
+            if !has_charset {
+                let mut meta = Target::new("meta".to_string());
+                let mut c = Target::new("charset".to_string());
+                c.text.push(charset.clone());
+                meta.value.push(c);
+                head.value.insert(0, meta);
+            }
+        }
+        for t in [&mut head, &mut body] {
+            if t.value.is_empty() {
+                t.text.push(String::new());
+            }
+        }
+        html.value.push(head);
+        html.value.push(body);
+        let mut rst = String::from("<!DOCTYPE html>");
+        if options.output.indent.is_some() {
+            rst.push_str(options.output.line_ending.as_str());
+        }
+        rst.push_str(&self.convert_with(html, &options.output));
         rst
     }
 
-    //"global attributes" or "event handlers" or "custom data attribute"
+    ///Convert HTML fragment to `Vec<Target>`, the inverse of `convert`.
+    ///
+    ///Attributes known to the converter become child targets with the case of the source, an
+    ///attribute without value has no text, other attributes are dropped. An element with only text keeps it as its text, otherwise
+    ///text between child elements becomes a `#text` child. Text of only whitespace with a line break is dropped.
+    ///Unmatched end tags are ignored and open elements are closed at the end of input.
+    ///Elements nested more than 1024 levels deep are closed at once, so their children become siblings.
+    pub fn html_to_target(&self, html: &str) -> Vec<Target> {
+        let html = html.replace("\r\n", "\n").replace('\r', "\n");
+        HtmlParser {
+            chars: html.chars().collect(),
+            i: 0,
+            text: String::new(),
+            stack: Vec::new(),
+            roots: Vec::new(),
+            converter: self,
+        }
+        .parse()
+    }
+
+    //"global attributes" or "event handlers" or prefix like "custom data attribute"
     fn is_attr(&self, str: &str) -> bool {
-        self.globals.contains(str) || self.event_handler.contains(str) || str.starts_with(DATA)
+        let str = str.to_lowercase();
+        self.globals.contains(&str) || self.prefixes.iter().any(|p| str.starts_with(p.as_str()))
+    }
+
+    //special case
+    fn is_element(&self, element: &str, s: &str) -> bool {
+        self.exceptions
+            .contains(&(element.to_lowercase(), s.to_lowercase()))
     }
 
     //attributes on the element
     fn is_element_attr(&self, element: &str, s: &str) -> bool {
         if let Some(attributes) = self.element_attributes.get(element.to_lowercase().as_str()) {
-            return attributes.contains(s);
+            return attributes.contains(&s.to_lowercase());
         }
         false
     }
 
-    fn convert_target(&self, rst: &mut String, target: Target) {
+    //attribute of the element, not child element
+    fn is_attribute(&self, element: &str, s: &str) -> bool {
+        (self.is_attr(s) && !self.is_element(element, s)) || self.is_element_attr(element, s)
+    }
+
+    //depth first with a stack of open elements, each element is closed around its children
+    fn convert_target(&self, rst: &mut String, target: Target, layout: Layout) {
+        let mut stack: Vec<Open> = self.open(rst, target, layout).into_iter().collect();
+        while let Some(top) = stack.last_mut() {
+            let Some(child) = top.children.next() else {
+                let Some(top) = stack.pop() else {
+                    break;
+                };
+                let out = match stack.last_mut() {
+                    Some(parent) => &mut parent.child_str,
+                    None => &mut *rst,
+                };
+                top.close(out);
+                continue;
+            };
+            let c = &child.name;
+            if self.is_attribute(&top.name, c) {
+                if child.text.is_empty() {
+                    top.attributes.push(attribute_name(c).into_owned());
+                } else {
+                    add_attribute(&mut top.attributes, c, &child.text);
+                }
+            } else if c == TEXT && !top.is_void && top.is_raw {
+                for t in &child.text {
+                    push_raw_text(&mut top.child_str, &top.name, t);
+                }
+            } else {
+                let child_layout = top.child_layout;
+                if let Some(open) = self.open(&mut top.child_str, child, child_layout) {
+                    stack.push(open);
+                }
+            }
+        }
+    }
+
+    //add texts of target, and return the element to add its children to, unless it is text
+    fn open<'a>(&self, rst: &mut String, target: Target, layout: Layout<'a>) -> Option<Open<'a>> {
         let n = &target.name;
-        for t in target.text {
-            add_tag(rst, n, &t);
+        if n == RAW {
+            for t in target.text {
+                rst.push_str(&t);
+            }
+            return None;
         }
-        let mut attr_str = String::new();
+        if n == TEXT {
+            for t in target.text {
+                push_text(rst, &t);
+            }
+            return None;
+        }
+        //void element has no content, its texts and children follow the tag
+        let is_void = is_void_element(n);
+        let is_raw = is_raw_text_element(n);
+        //mixed content stays on one line, whitespace between its children would be visible
+        let inline = is_raw
+            || target
+                .value
+                .iter()
+                .any(|c| (c.name == TEXT || c.name == RAW) && !self.is_attribute(n, &c.name));
+        let child_layout = if inline {
+            Layout::compact()
+        } else if is_void {
+            layout
+        } else {
+            layout.child()
+        };
         let mut child_str = String::new();
-        for child in target.value {
-            let c = &child.name;
-            if (self.is_attr(c) && !is_element(n, c)) || self.is_element_attr(n, c) {
-                add_attribute(&mut attr_str, c, &child.text);
+        if is_void {
+            for t in target.text {
+                child_layout.start_line(&mut child_str);
+                push_text(&mut child_str, &t);
+                child_layout.end_line(&mut child_str);
+            }
+        } else if is_raw {
+            for t in target.text {
+                let mut s = String::new();
+                push_raw_text(&mut s, n, &t);
+                add_tag_with_content(rst, layout, n, &[], &s);
+            }
+        } else {
+            for t in target.text {
+                add_tag(rst, layout, n, &t);
+            }
+        }
+        Some(Open {
+            name: target.name,
+            is_void,
+            is_raw,
+            inline,
+            layout,
+            child_layout,
+            attributes: Vec::new(),
+            child_str,
+            children: target.value.into_iter(),
+        })
+    }
+}
+
+//element whose children are being converted
+struct Open<'a> {
+    name: String,
+    is_void: bool,
+    is_raw: bool,
+    inline: bool,
+    layout: Layout<'a>,
+    child_layout: Layout<'a>,
+    attributes: Vec<String>,
+    child_str: String,
+    children: std::vec::IntoIter<Target>,
+}
+
+impl Open<'_> {
+    fn close(self, rst: &mut String) {
+        let n = &self.name;
+        if self.is_void {
+            add_start_tag(rst, self.layout, n, &self.attributes);
+            rst.push_str(&self.child_str);
+        } else if self.inline && (!self.attributes.is_empty() || !self.child_str.is_empty()) {
+            add_tag_with_content(rst, self.layout, n, &self.attributes, &self.child_str);
+        } else if !self.attributes.is_empty() || !self.child_str.is_empty() {
+            add_tag_with_attribute(rst, self.layout, n, &self.attributes, &self.child_str);
+        }
+    }
+}
+
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        n => {
+            let code = if let Some(h) = n.strip_prefix("#x").or_else(|| n.strip_prefix("#X")) {
+                u32::from_str_radix(h, 16).ok()
+            } else if let Some(d) = n.strip_prefix('#') {
+                d.parse().ok()
             } else {
-                self.convert_target(&mut child_str, child);
+                None
+            };
+            code.filter(|c| *c != 0).and_then(char::from_u32)
+        }
+    }
+}
+
+//unknown character reference is kept as is
+fn decode(s: &str) -> String {
+    let mut rst = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        rst.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        //the name ends at the first char that cannot be in it
+        match rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '#')
+            .filter(|&n| rest[n..].starts_with(';'))
+            .and_then(|n| entity(&rest[..n]).map(|c| (c, n)))
+        {
+            Some((c, n)) => {
+                rst.push(c);
+                rest = &rest[n + 1..];
             }
+            None => rst.push('&'),
         }
-        if attr_str.len() > 0 || child_str.len() > 0 {
-            add_tag_with_attribute(rst, n, &attr_str, &child_str);
+    }
+    rst.push_str(rest);
+    rst
+}
+
+//open element
+struct Element {
+    name: String,
+    attributes: Vec<Target>,
+    nodes: Vec<Target>,
+}
+
+impl Element {
+    fn into_target(mut self) -> Target {
+        let mut t = Target::new(self.name);
+        if self.attributes.is_empty() && self.nodes.len() == 1 && self.nodes[0].name == TEXT {
+            t.text = self.nodes.remove(0).text;
+        } else if self.attributes.is_empty() && self.nodes.is_empty() {
+            if !is_void_element(&t.name) {
+                t.text.push(String::new());
+            }
+        } else {
+            t.value = self.attributes;
+            t.value.append(&mut self.nodes);
+        }
+        t
+    }
+}
+
+struct HtmlParser<'a> {
+    chars: Vec<char>,
+    i: usize,
+    //pending text, not decoded
+    text: String,
+    stack: Vec<Element>,
+    roots: Vec<Target>,
+    converter: &'a Converter,
+}
+
+impl HtmlParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.i).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(n, c)| self.chars.get(self.i + n) == Some(&c))
+    }
+
+    fn starts_with_ignore_case(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(n, c)| {
+            self.chars
+                .get(self.i + n)
+                .is_some_and(|a| a.eq_ignore_ascii_case(&c))
+        })
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.i;
+        while self.peek().is_some_and(&f) {
+            self.i += 1;
+        }
+        self.chars[start..self.i].iter().collect()
+    }
+
+    fn skip_past(&mut self, end: &str) {
+        while self.i < self.chars.len() && !self.starts_with(end) {
+            self.i += 1;
+        }
+        self.i = (self.i + end.chars().count()).min(self.chars.len());
+    }
+
+    fn nodes(&mut self) -> &mut Vec<Target> {
+        match self.stack.last_mut() {
+            Some(e) => &mut e.nodes,
+            None => &mut self.roots,
+        }
+    }
+
+    fn push_text(&mut self, s: String) {
+        if s.is_empty() {
+            return;
+        }
+        let nodes = self.nodes();
+        match nodes.last_mut() {
+            Some(t) if t.name == TEXT => t.text[0].push_str(&s),
+            _ => {
+                let mut t = Target::new(TEXT.to_string());
+                t.text.push(s);
+                nodes.push(t);
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        let s = std::mem::take(&mut self.text);
+        if !(s.contains('\n') && s.chars().all(char::is_whitespace)) {
+            self.push_text(decode(&s));
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(e) = self.stack.pop() {
+            let t = e.into_target();
+            self.nodes().push(t);
+        }
+    }
+
+    fn parse(mut self) -> Vec<Target> {
+        while let Some(c) = self.peek() {
+            let next = self.chars.get(self.i + 1).copied().unwrap_or(' ');
+            if c != '<' {
+                self.text.push(c);
+                self.i += 1;
+            } else if self.starts_with("<!--") {
+                self.flush();
+                self.i += 4;
+                self.skip_past("-->");
+            } else if matches!(next, '!' | '?' | '/') {
+                self.flush();
+                self.i += 2;
+                let end = next == '/' && self.peek().is_some_and(|c| c.is_ascii_alphabetic());
+                let name = self.take_while(|c| !c.is_whitespace() && c != '>');
+                self.skip_past(">");
+                if end {
+                    let name = name.to_lowercase();
+                    if let Some(p) = self.stack.iter().rposition(|e| e.name == name) {
+                        while self.stack.len() > p {
+                            self.close();
+                        }
+                    }
+                }
+            } else if next.is_ascii_alphabetic() {
+                self.flush();
+                self.start_tag();
+            } else {
+                self.text.push(c);
+                self.i += 1;
+            }
+        }
+        self.flush();
+        while !self.stack.is_empty() {
+            self.close();
+        }
+        self.roots
+    }
+
+    //at '<'
+    fn start_tag(&mut self) {
+        self.i += 1;
+        let name = self
+            .take_while(|c| !c.is_whitespace() && c != '/' && c != '>')
+            .to_lowercase();
+        let mut e = Element {
+            name,
+            attributes: Vec::new(),
+            nodes: Vec::new(),
+        };
+        let mut self_closing = false;
+        loop {
+            self.take_while(char::is_whitespace);
+            match self.peek() {
+                None => break,
+                Some('>') => {
+                    self.i += 1;
+                    break;
+                }
+                Some('/') => {
+                    self.i += 1;
+                    self_closing = self.peek() == Some('>');
+                }
+                Some(_) => {
+                    let mut a =
+                        self.take_while(|c| !c.is_whitespace() && c != '/' && c != '>' && c != '=');
+                    if a.is_empty() {
+                        //'=' without name
+                        a.push('=');
+                        self.i += 1;
+                    }
+                    self.take_while(char::is_whitespace);
+                    let mut value = None;
+                    if self.peek() == Some('=') {
+                        self.i += 1;
+                        self.take_while(char::is_whitespace);
+                        value = Some(match self.peek() {
+                            Some(q @ ('"' | '\'')) => {
+                                self.i += 1;
+                                let v = self.take_while(|c| c != q);
+                                if self.peek() == Some(q) {
+                                    self.i += 1;
+                                }
+                                v
+                            }
+                            _ => self.take_while(|c| !c.is_whitespace() && c != '>'),
+                        });
+                    }
+                    if self.converter.is_attribute(&e.name, &a)
+                        && !e.attributes.iter().any(|t| t.name.eq_ignore_ascii_case(&a))
+                    {
+                        let mut t = Target::new(a);
+                        t.text.extend(value.map(|v| decode(&v)));
+                        e.attributes.push(t);
+                    }
+                }
+            }
+        }
+        let raw = is_raw_text_element(&e.name);
+        let rcdata = RCDATA_ELEMENTS.contains(&e.name.as_str());
+        let name = e.name.clone();
+        self.stack.push(e);
+        if is_void_element(&name) || self_closing {
+            self.close();
+        } else if raw || rcdata {
+            let end = format!("</{}", name);
+            let start = self.i;
+            while self.i < self.chars.len() && !self.starts_with_ignore_case(&end) {
+                self.i += 1;
+            }
+            let s: String = self.chars[start..self.i].iter().collect();
+            self.push_text(if rcdata { decode(&s) } else { s });
+        } else if self.stack.len() > MAX_DEPTH {
+            //too deep, children become siblings
+            self.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::try_chars_to_target;
+
+    fn convert(s: &str) -> String {
+        let c = Converter::new();
+        let t = try_chars_to_target(s.chars()).unwrap();
+        t.into_iter().map(|t| c.convert(t)).collect()
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            convert("0~div\n1~p:<img src=x onerror=alert(1)>\n1~title:\"><b>\n"),
+            "<div title=\"&quot;>&lt;b>\"><p>&lt;img src=x onerror=alert(1)&gt;</p></div>"
+        );
+        assert_eq!(
+            convert("0~img src=x onerror=alert(1):a\n"),
+            "<img_src_x_onerror_alert_1_>a</img_src_x_onerror_alert_1_>"
+        );
+        assert_eq!(
+            convert("0~div\n1~#raw:<b>trusted</b>\n"),
+            "<div><b>trusted</b></div>"
+        );
+        assert_eq!(
+            convert("0~script:if (a < b && c) s = \"</SCRIPT><b>\"\n"),
+            "<script>if (a < b && c) s = \"<\\/SCRIPT><b>\"</script>"
+        );
+        assert_eq!(
+            convert("0~style:a > b { x: \"</p>\" }\n"),
+            "<style>a > b { x: \"</p>\" }</style>"
+        );
+        assert_eq!(
+            convert("0~div\n1~data-q><script>alert(2)</script:x\n"),
+            "<div data-q__script_alert(2)__script=\"x\"></div>"
+        );
+        assert_eq!(convert("0~input\n1~data-a b/>\n"), "<input data-a_b__>");
+    }
+
+    #[test]
+    fn test_void() {
+        assert_eq!(
+            convert("0~p\n1~img\n2~src:a.png\n2~alt:a\n1~br\n1~input\n2~disabled\n2~hidden\n"),
+            "<p><img src=\"a.png\" alt=\"a\"><br><input disabled hidden></p>"
+        );
+        assert_eq!(convert("0~hr:x\n1~span:y\n"), "<hr>x<span>y</span>");
+    }
+
+    #[test]
+    fn test_builder() {
+        let t = try_chars_to_target(
+            "0~my-widget\n1~role:button\n1~aria-label:x\n1~data-a:1\n1~svg\n2~viewBox:0 0 1 1\n2~title:t\n"
+                .chars(),
+        )
+        .unwrap();
+        let c = Converter::new()
+            .add_global("role")
+            .add_prefix("aria-")
+            .remove_prefix("data-")
+            .add_element_attribute("SVG", "viewBox")
+            .add_exception("svg", "title");
+        assert_eq!(
+            c.convert(t[0].clone()),
+            "<my-widget role=\"button\" aria-label=\"x\"><data-a>1</data-a>\
+             <svg viewBox=\"0 0 1 1\"><title>t</title></svg></my-widget>"
+        );
+        let c = c.remove_global("role").remove_exception("svg", "title");
+        assert_eq!(
+            c.convert(t[0].clone()),
+            "<my-widget aria-label=\"x\"><role>button</role><data-a>1</data-a>\
+             <svg viewBox=\"0 0 1 1\" title=\"t\"></svg></my-widget>"
+        );
+        //registered names match in any case, and import keeps the case
+        let html = "<svg viewBox=\"0 0 1 1\" ID=\"a\"></svg>";
+        let c = Converter::new().add_element_attribute("svg", "viewBox");
+        let t = c.html_to_target(html);
+        assert_eq!(t[0].value[0].name, "viewBox");
+        assert_eq!(c.convert(t[0].clone()), html);
+    }
+
+    #[test]
+    fn test_document() {
+        let c = Converter::new();
+        let t = try_chars_to_target(
+            "0~title:t\n0~meta\n1~name:a\n0~p:a\n0~html\n1~lang:en\n1~body\n2~class:x\n".chars(),
+        )
+        .unwrap();
+        let mut options = DocumentOptions::new();
+        options.lift_head = true;
+        assert_eq!(
+            c.convert_document(t.clone(), &options),
+            "<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\"><title>t</title>\
+             <meta name=\"a\"></head><body class=\"x\"><p>a</p></body></html>"
+        );
+        options.lift_head = false;
+        options.charset = None;
+        options.output = OutputOptions::pretty(1);
+        assert_eq!(
+            c.convert_document(t[1..].to_vec(), &options),
+            "<!DOCTYPE html>\n<html lang=\"en\">\n <head></head>\n <body class=\"x\">\n\
+             \x20 <meta name=\"a\">\n  <p>a</p>\n </body>\n</html>\n"
+        );
+        assert_eq!(
+            c.convert_document(Vec::new(), &DocumentOptions::new()),
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"></head><body></body></html>"
+        );
+        let t =
+            try_chars_to_target("0~html:a\n1~head:b\n2~meta\n3~CHARSET:latin1\n1~body:c\n".chars())
+                .unwrap();
+        assert_eq!(
+            c.convert_document(t, &DocumentOptions::new()),
+            "<!DOCTYPE html><html><head><meta CHARSET=\"latin1\"></head><body>abc</body></html>"
+        );
+    }
+
+    #[test]
+    fn test_html_to_target() {
+        let c = Converter::new();
+        let html = "<div class=\"a &amp; b\" id=x aria-label=\"y\">
+  <p>Hello <b>world</b> &lt;&#33;&gt;</p>
+  <a href=\"/\">home</a><br/><img src=\"x.png\" alt=\"\"><input disabled>
+  <p></p><!-- comment -->
+  <script type=module>if (a < b) {}</script><textarea>&lt;b></TEXTAREA>
+</div></span>tail";
+        let t = c.html_to_target(html);
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].value[0].text, vec!["a & b"]);
+        assert_eq!(t[0].value[2].name, "p");
+        assert_eq!(t[1].name, TEXT);
+        let s: String = t.into_iter().map(|t| c.convert(t)).collect();
+        assert_eq!(
+            s,
+            "<div class=\"a &amp; b\" id=\"x\"><p>Hello <b>world</b> &lt;!&gt;</p>\
+             <a href=\"/\">home</a><br><img src=\"x.png\" alt=\"\"><input disabled>\
+             <p></p><script type=\"module\">if (a < b) {}</script><textarea>&lt;b&gt;</textarea></div>tail"
+        );
+        let again: String = c
+            .html_to_target(&s)
+            .into_iter()
+            .map(|t| c.convert(t))
+            .collect();
+        assert_eq!(again, s);
+        //value without closing quote
+        for html in ["<div a=\"x", "<p title='x", "<a b='"] {
+            assert_eq!(c.html_to_target(html).len(), 1);
         }
+        assert_eq!(c.html_to_target("<p title='x")[0].value[0].text, vec!["x"]);
+        let t = c.html_to_target(&format!("<p>{}&amp;", "&".repeat(100_000)));
+        assert_eq!(t[0].text[0].len(), 100_001);
     }
 }
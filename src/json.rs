@@ -0,0 +1,547 @@
+//!Convert between `Target` and JSON.
+//!
+//!* a list of targets is an object, keys in order of first occurrence. Siblings with the same name are an array.
+//!
+//!* a target with one text is a string, with more texts an array of strings, with nothing `null`.
+//!
+//!* a target with children is an object. If it has text too (mixed node), the text is under the key `#text`.
+//!
+//!* a child named `#text`, or with a name starting with `##`, gets one more `#` before its name.
+//!
+//!On the way back, numbers and bools become text as written, an array of scalars becomes one target with
+//!an array text, and any other array becomes one target per element. So repeated keys that only have text
+//!come back as one target, like the default grouped parse. Nested arrays like `[[1]]` are rejected.
+
+use crate::options::MAX_DEPTH;
+use crate::Target;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+//key of text in a mixed node
+const TEXT: &str = "#text";
+
+///Error when parse JSON.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    pub message: String,
+    ///Row number, starting at 1.
+    pub row: usize,
+    ///Column number in chars, starting at 1.
+    pub column: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.message, self.row, self.column)
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn push_string(s: &mut String, str: &str) {
+    s.push('"');
+    for c in str.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if (c as u32) < 0x20 => s.push_str(&format!("\\u{:04x}", c as u32)),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+fn push_texts(s: &mut String, text: &[String]) {
+    if text.len() == 1 {
+        push_string(s, &text[0]);
+    } else {
+        s.push('[');
+        for (i, t) in text.iter().enumerate() {
+            if i > 0 {
+                s.push(',');
+            }
+            push_string(s, t);
+        }
+        s.push(']');
+    }
+}
+
+//child names that would be read as text or lose a '#' get one more '#'
+fn escape_name(name: &str) -> Cow<'_, str> {
+    if name == TEXT || name.starts_with("##") {
+        Cow::Owned(format!("#{}", name))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+fn unescape_name(mut name: String) -> String {
+    if name.starts_with("##") {
+        name.remove(0);
+    }
+    name
+}
+
+//open object, siblings with the same name are grouped into an array
+struct Object<'a> {
+    groups: Vec<(&'a str, Vec<&'a Target>)>,
+    //next group and next target in it
+    group: usize,
+    item: usize,
+}
+
+impl<'a> Object<'a> {
+    fn open(s: &mut String, targets: &'a [Target], text: &[String]) -> Self {
+        let mut groups: Vec<(&str, Vec<&Target>)> = Vec::new();
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        for t in targets {
+            let i = *index.entry(&t.name).or_insert(groups.len());
+            if i == groups.len() {
+                groups.push((&t.name, Vec::new()));
+            }
+            groups[i].1.push(t);
+        }
+        s.push('{');
+        if !text.is_empty() {
+            push_string(s, TEXT);
+            s.push(':');
+            push_texts(s, text);
+            if !groups.is_empty() {
+                s.push(',');
+            }
+        }
+        Object {
+            groups,
+            group: 0,
+            item: 0,
+        }
+    }
+}
+
+//depth first with a stack of open objects
+fn push_object(s: &mut String, targets: &[Target], text: &[String]) {
+    let mut stack = vec![Object::open(s, targets, text)];
+    while let Some(top) = stack.last_mut() {
+        let Some((name, group)) = top.groups.get(top.group) else {
+            s.push('}');
+            stack.pop();
+            continue;
+        };
+        if top.item == group.len() {
+            if group.len() > 1 {
+                s.push(']');
+            }
+            top.group += 1;
+            top.item = 0;
+            continue;
+        }
+        if top.item == 0 {
+            if top.group > 0 {
+                s.push(',');
+            }
+            push_string(s, &escape_name(name));
+            s.push(':');
+            if group.len() > 1 {
+                s.push('[');
+            }
+        } else {
+            s.push(',');
+        }
+        let target = group[top.item];
+        top.item += 1;
+        if !target.value.is_empty() {
+            stack.push(Object::open(s, &target.value, &target.text));
+        } else if target.text.is_empty() {
+            s.push_str("null");
+        } else {
+            push_texts(s, &target.text);
+        }
+    }
+}
+
+///Convert `Vec<Target>` to a JSON object.
+pub fn vec_to_json(vec: &[Target]) -> String {
+    let mut s = String::new();
+    push_object(&mut s, vec, &[]);
+    s
+}
+
+///Convert `Target` to a JSON object with one key.
+pub fn to_json(target: &Target) -> String {
+    vec_to_json(std::slice::from_ref(target))
+}
+
+///Parse a JSON object to `Vec<Target>`.
+pub fn json_to_target(s: &str) -> Result<Vec<Target>, Error> {
+    let mut p = JsonParser {
+        chars: s.chars().collect(),
+        i: 0,
+        depth: 0,
+    };
+    p.space();
+    let v = p.value()?;
+    p.space();
+    if p.i < p.chars.len() {
+        return Err(p.error("trailing characters"));
+    }
+    match v {
+        Value::Object(members) => {
+            let mut vec = Vec::new();
+            for (k, v) in members {
+                push_target(&mut vec, unescape_name(k), v);
+            }
+            Ok(vec)
+        }
+        _ => Err(Error {
+            message: "expected an object".to_string(),
+            row: 1,
+            column: 1,
+        }),
+    }
+}
+
+enum Value {
+    Null,
+    //string, number or bool, as text
+    Scalar(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+//nested arrays and objects in #text are rejected by the parser
+fn push_target(vec: &mut Vec<Target>, name: String, v: Value) {
+    let mut target = Target::new(name);
+    match v {
+        Value::Null => {}
+        Value::Scalar(s) => target.text.push(s),
+        Value::Array(a) => {
+            if a.iter().all(|v| matches!(v, Value::Scalar(_))) {
+                for v in a {
+                    if let Value::Scalar(s) = v {
+                        target.text.push(s);
+                    }
+                }
+            } else {
+                for v in a {
+                    push_target(vec, target.name.clone(), v);
+                }
+                return;
+            }
+        }
+        Value::Object(members) => {
+            for (k, v) in members {
+                if k == TEXT {
+                    match v {
+                        Value::Scalar(s) => target.text.push(s),
+                        Value::Array(a) => {
+                            for v in a {
+                                if let Value::Scalar(s) = v {
+                                    target.text.push(s);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                } else {
+                    push_target(&mut target.value, unescape_name(k), v);
+                }
+            }
+        }
+    }
+    vec.push(target);
+}
+
+fn is_texts(v: &Value) -> bool {
+    match v {
+        Value::Scalar(_) | Value::Null => true,
+        Value::Array(a) => a.iter().all(|v| matches!(v, Value::Scalar(_))),
+        Value::Object(_) => false,
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    i: usize,
+    //open objects and arrays
+    depth: usize,
+}
+
+impl JsonParser {
+    fn error(&self, message: &str) -> Error {
+        let mut row = 1;
+        let mut column = 1;
+        for c in &self.chars[..self.i.min(self.chars.len())] {
+            if *c == '\n' {
+                row += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        Error {
+            message: message.to_string(),
+            row,
+            column,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.i).copied()
+    }
+
+    fn space(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.i += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.peek() == Some(c) {
+            self.i += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {:?}", c)))
+        }
+    }
+
+    fn literal(&mut self, s: &str) -> Result<(), Error> {
+        for c in s.chars() {
+            self.expect(c)?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        match self.peek() {
+            Some('{' | '[') if self.depth == MAX_DEPTH => Err(self.error("nesting is too deep")),
+            Some('{') => {
+                self.depth += 1;
+                let v = self.object();
+                self.depth -= 1;
+                v
+            }
+            Some('[') => {
+                self.depth += 1;
+                let v = self.array();
+                self.depth -= 1;
+                v
+            }
+            Some('"') => Ok(Value::Scalar(self.string()?)),
+            Some('t') => self
+                .literal("true")
+                .map(|_| Value::Scalar("true".to_string())),
+            Some('f') => self
+                .literal("false")
+                .map(|_| Value::Scalar("false".to_string())),
+            Some('n') => self.literal("null").map(|_| Value::Null),
+            Some('-' | '0'..='9') => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, Error> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.space();
+        if self.peek() == Some('}') {
+            self.i += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.space();
+            let k = self.string()?;
+            self.space();
+            self.expect(':')?;
+            self.space();
+            let v = self.value()?;
+            if k == TEXT && !is_texts(&v) {
+                return Err(self.error("#text must be a string or an array of strings"));
+            }
+            members.push((k, v));
+            self.space();
+            match self.peek() {
+                Some(',') => self.i += 1,
+                Some('}') => {
+                    self.i += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, Error> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.space();
+        if self.peek() == Some(']') {
+            self.i += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            self.space();
+            if self.peek() == Some('[') {
+                return Err(self.error("nested arrays are not supported"));
+            }
+            values.push(self.value()?);
+            self.space();
+            match self.peek() {
+                Some(',') => self.i += 1,
+                Some(']') => {
+                    self.i += 1;
+                    return Ok(Value::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let start = self.i;
+        if self.peek() == Some('-') {
+            self.i += 1;
+        }
+        let digits = |p: &mut Self| {
+            let s = p.i;
+            while let Some('0'..='9') = p.peek() {
+                p.i += 1;
+            }
+            p.i > s
+        };
+        if !digits(self) {
+            return Err(self.error("expected a digit"));
+        }
+        if self.peek() == Some('.') {
+            self.i += 1;
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        if let Some('e' | 'E') = self.peek() {
+            self.i += 1;
+            if let Some('+' | '-') = self.peek() {
+                self.i += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        Ok(Value::Scalar(self.chars[start..self.i].iter().collect()))
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let d = self
+                .peek()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("expected a hexadecimal digit"))?;
+            n = n * 16 + d;
+            self.i += 1;
+        }
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.i += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let e = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.i += 1;
+                    match e {
+                        '"' | '\\' | '/' => s.push(e),
+                        'b' => s.push('\u{8}'),
+                        'f' => s.push('\u{C}'),
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        'u' => {
+                            let mut n = self.hex4()?;
+                            if (0xD800..0xDC00).contains(&n) {
+                                self.literal("\\u")?;
+                                let low = self.hex4()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err(self.error("invalid surrogate pair"));
+                                }
+                                n = 0x10000 + ((n - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            s.push(char::from_u32(n).ok_or_else(|| self.error("invalid escape"))?);
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                c => s.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::try_chars_to_target;
+
+    #[test]
+    fn test_round_trip() {
+        let s = "0~a:a
++a
+|b
+0~b:x
+1~c:1
+1~c:2
+1~d
+2~e:\"q\"
+1~d
+0~f
+";
+        let t = try_chars_to_target(s.chars()).unwrap();
+        let json = vec_to_json(&t);
+        assert_eq!(
+            json,
+            r##"{"a":["a","a\nb"],"b":{"#text":"x","c":["1","2"],"d":{"e":"\"q\""}},"f":null}"##
+        );
+        assert_eq!(json_to_target(&json).unwrap(), t);
+
+        let t = json_to_target(
+            r#" {"n": [1, -2.5e3, true], "o": [{"p": "\u00e9\ud83d\ude00"}, "s"]} "#,
+        )
+        .unwrap();
+        assert_eq!(t[0].text, vec!["1", "-2.5e3", "true"]);
+        assert_eq!(t[1].value[0].text, vec!["é😀"]);
+        assert_eq!(t[2].text, vec!["s"]);
+        let e = json_to_target("{\n\"a\": [1,]}").unwrap_err();
+        assert_eq!((e.row, e.column), (2, 9));
+        assert!(json_to_target(r#"{"a": [[1]]}"#).is_err());
+        assert!(json_to_target(r##"{"a": {"#text": {}}}"##).is_err());
+        //many distinct siblings
+        let t: Vec<Target> = (0..100_000).map(|i| Target::new(i.to_string())).collect();
+        assert_eq!(json_to_target(&vec_to_json(&t)).unwrap().len(), t.len());
+        assert!(json_to_target("[]").is_err());
+
+        //children named like the text key
+        let s = "0~p\n1~#text:Hello \n1~b:world\n0~q:x\n1~#text:y\n1~##z:1\n";
+        let t = try_chars_to_target(s.chars()).unwrap();
+        let json = vec_to_json(&t);
+        assert_eq!(
+            json,
+            r####"{"p":{"##text":"Hello ","b":"world"},"q":{"#text":"x","##text":"y","###z":"1"}}"####
+        );
+        assert_eq!(json_to_target(&json).unwrap(), t);
+        let html = crate::html::Converter::new().convert(json_to_target(&json).unwrap().remove(0));
+        assert_eq!(html, "<p>Hello <b>world</b></p>");
+    }
+}
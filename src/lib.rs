@@ -2,7 +2,7 @@
 //!
 //!text.
 //!
-//!```
+//!```text
 //!0~a:a
 //!+a
 //!|b
@@ -18,7 +18,7 @@
 //!
 //!object.
 //!
-//!```
+//!```text
 //![Target { name: "a", text: ["a", "a\nb\nc"], value: [] },
 //!
 //!Target { name: "b", text: ["a"], value: [Target { name: "a", text: [], value: [Target { name: "a", text: ["b"], value: [] }] }] },
@@ -35,15 +35,46 @@
 
 #![allow(dead_code)]
 
+mod borrowed;
 mod contents;
+///Lossless syntax tree, for formatters and editors.
+pub mod cst;
+///Convert text format to `Deserialize` types.
+#[cfg(feature = "serde")]
+pub mod de;
+mod error;
+mod format;
 mod header;
 ///To HTML String.
 pub mod html;
+#[cfg(feature = "json")]
+pub mod json;
+mod options;
 mod parser;
+///Convert `Serialize` types to text format.
+#[cfg(feature = "serde")]
+pub mod ser;
+mod span;
+mod stream;
 mod tree;
+mod utf8;
+mod writer;
 mod xml;
+mod xml_reader;
 
+pub use crate::borrowed::*;
 pub use crate::contents::*;
+#[cfg(feature = "serde")]
+pub use crate::de::from_str;
+pub use crate::error::*;
+pub use crate::format::*;
+pub use crate::options::*;
+#[cfg(feature = "serde")]
+pub use crate::ser::to_string;
+pub use crate::span::*;
+pub use crate::stream::*;
+pub use crate::writer::*;
+pub use crate::xml_reader::*;
 
 #[cfg(test)]
 mod tests {
@@ -64,9 +95,248 @@ mod tests {
 |cc
 |
 ";
-        let t = parse_chars_to_target(s.chars(), |r, n| println!("err ({},{})", r, n));
+        let t = chars_to_target(s.chars(), |r, n| println!("err ({},{})", r, n));
         println!("{:?}", t);
         assert_eq!(t[1].text[0], "a");
         assert_eq!(t[1].value[0].value[0].text[0], "b");
     }
+
+    #[test]
+    fn test_parse_error() {
+        let s = "0~a:a
+2~b:b
+0~:c
+0~d:d
++d
+";
+        let e = try_chars_to_target(s.chars()).unwrap_err();
+        assert_eq!(e.len(), 2);
+        assert_eq!(e[0].kind, ParseErrorKind::OffsetJump);
+        assert_eq!((e[0].row, e[0].column), (2, 2));
+        assert_eq!(e[0].char, Some('~'));
+        assert_eq!(e[0].state, "accept_offset");
+        assert_eq!(e[1].kind, ParseErrorKind::EmptyKey);
+        assert_eq!((e[1].row, e[1].column), (3, 3));
+        assert!(try_chars_to_target("0~a:a\n1~b".chars()).is_ok());
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        let s = "0~a:a\n2~b:b\n0~:c\n";
+        let mut options = ParseOptions::new();
+        options.strict = true;
+        let e = chars_to_document_with(s.chars(), &options).unwrap_err();
+        assert_eq!(e.len(), 1);
+        assert_eq!(e[0].kind, ParseErrorKind::OffsetJump);
+        let e = from_reader_with(s.as_bytes(), &options).unwrap_err();
+        assert!(matches!(e, ReadError::Parse(e) if e.len() == 1));
+        assert_eq!(
+            str_to_borrowed_document_with(s, &options)
+                .unwrap_err()
+                .len(),
+            1
+        );
+        assert!(chars_to_document_with("0~a:a\n1~b".chars(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_limits() {
+        let check = |s: &str, limits: Limits| {
+            let mut options = ParseOptions::new();
+            options.limits = limits;
+            let e = chars_to_document_with(s.chars(), &options).unwrap_err();
+            assert_eq!(e.len(), 1);
+            (e[0].kind, e[0].row)
+        };
+        let mut limits = Limits::new();
+        limits.max_depth = Some(2);
+        assert_eq!(
+            check("0~a\n1~b\n2~c\n0~:d", limits),
+            (ParseErrorKind::TooDeep, 3)
+        );
+        let mut limits = Limits::new();
+        limits.max_key_len = Some(2);
+        assert_eq!(
+            check("0~ab\n0~a\\bc", limits),
+            (ParseErrorKind::KeyTooLong, 2)
+        );
+        let mut limits = Limits::new();
+        limits.max_text_len = Some(3);
+        assert_eq!(
+            check("0~a:abc\n0~a:a\n|bc", limits),
+            (ParseErrorKind::TextTooLong, 3)
+        );
+        let mut limits = Limits::new();
+        limits.max_array_items = Some(2);
+        assert_eq!(
+            check("0~a:a\n+b\n+c", limits),
+            (ParseErrorKind::TooManyItems, 3)
+        );
+        let mut limits = Limits::new();
+        limits.max_nodes = Some(2);
+        assert_eq!(
+            check("0~a\n1~b\n0~c", limits),
+            (ParseErrorKind::TooManyNodes, 3)
+        );
+        let mut options = ParseOptions::new();
+        options.limits = Limits::untrusted();
+        assert!(chars_to_document_with("0~a:a\n1~b".chars(), &options).is_ok());
+        //offset with many leading zeros
+        let s = format!("0~a\n{}1~b", "0".repeat(100));
+        assert!(chars_to_document_with(s.chars(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_deep_convert() {
+        let depth = 10_000;
+        let deep = || {
+            let mut s = String::new();
+            for i in 0..depth {
+                s.push_str(&format!("{:x}~a\n", i));
+            }
+            s.push_str(&format!("{:x}~a:x\n", depth));
+            let mut options = ParseOptions::new();
+            options.limits.max_depth = None;
+            chars_to_document_with(s.chars(), &options).unwrap().targets
+        };
+        assert_eq!(vec_to_xml(deep()).len(), 7 * (depth + 1) + 1);
+        let map = vec_to_map(deep(), ".");
+        assert_eq!(map.values().next().unwrap(), &vec!["x".to_string()]);
+        let html = html::Converter::new().convert(deep().remove(0));
+        assert_eq!(html.len(), 7 * (depth + 1) + 1);
+    }
+
+    #[test]
+    fn test_deep_input() {
+        let deep = |depth: usize| {
+            let mut s = String::new();
+            for i in 0..depth {
+                s.push_str(&format!("{:x}~a\n", i));
+            }
+            s
+        };
+        let s = deep(150_000);
+        let e = try_chars_to_target(s.chars()).unwrap_err();
+        assert_eq!(
+            (e.len(), e[0].kind, e[0].row),
+            (1, ParseErrorKind::TooDeep, 1025)
+        );
+        assert!(from_bytes(s.as_bytes()).is_err());
+        assert!(str_to_borrowed_document(&s).is_err());
+        let mut errors = Vec::new();
+        let s = deep(1100) + "0~tail:x\n";
+        let t = chars_to_target(s.chars(), |r, c| errors.push((r, c)));
+        assert_eq!((t.len(), errors), (1, vec![(1025, 4)]));
+        let e = xml_to_target(&"<a>".repeat(150_000)).unwrap_err();
+        assert_eq!(e.column, 1024 * 3 + 1);
+        let t = html::Converter::new().html_to_target(&"<div>".repeat(150_000));
+        assert_eq!(t.len(), 1);
+        #[cfg(feature = "json")]
+        assert!(json::json_to_target(&"{\"a\":".repeat(150_000)).is_err());
+
+        //the deepest default input
+        let t = try_chars_to_target((deep(1023) + "3ff~a:x").chars()).unwrap();
+        assert_eq!(t.clone(), t);
+        assert!(format!("{:?}", t).len() > 1024);
+        let s = vec_to_text(&t).unwrap();
+        assert_eq!(try_chars_to_target(s.chars()).unwrap(), t);
+        #[cfg(feature = "json")]
+        assert_eq!(json::json_to_target(&json::vec_to_json(&t)).unwrap(), t);
+        assert_eq!(xml_to_target(&vec_to_xml(t)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let s = "#version 1
+#author:a
+##c: #fff
+0~a:a
+";
+        let d = chars_to_document(s.chars()).unwrap();
+        assert_eq!(d.header, vec!["version 1", "author:a", "#c: #fff"]);
+        assert_eq!(d.targets[0].text[0], "a");
+        let d = chars_to_document("#only".chars()).unwrap();
+        assert_eq!(d.header, vec!["only"]);
+        assert!(d.targets.is_empty());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let s = "\u{FEFF}0~a:\u{e9}\n1~b:\u{1F600}\n";
+        let d = from_bytes(s.as_bytes()).unwrap();
+        assert_eq!(d, chars_to_document(s[3..].chars()).unwrap());
+        //reader returning one byte at a time splits every char
+        struct OneByte<'a>(&'a [u8]);
+        impl std::io::Read for OneByte<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = self.0.len().min(buf.len()).min(1);
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+        assert_eq!(from_reader(OneByte(s.as_bytes())).unwrap(), d);
+
+        let e = from_bytes(b"0~a:x\n0~b:\xff\xfey\n0~c:\xe9").unwrap_err();
+        let positions: Vec<(usize, usize)> = e.iter().map(|e| (e.row, e.column)).collect();
+        assert_eq!(positions, vec![(2, 5), (2, 6), (3, 5)]);
+        assert!(e.iter().all(|e| e.kind == ParseErrorKind::InvalidUtf8));
+        match from_reader(OneByte(b"0~a:\xff")) {
+            Err(ReadError::Parse(e)) => assert_eq!(e[0].column, 5),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let s = "0~a:xy\n+z\n1~b\n0~c:1\n|2\n0~a:\u{e9}\r\n";
+        let mut options = ParseOptions::new();
+        options.spans = true;
+        let t = chars_to_document_with(s.chars(), &options).unwrap().targets;
+        let span = |r1, c1, r2, c2| Span {
+            start: Position {
+                row: r1,
+                column: c1,
+            },
+            end: Position {
+                row: r2,
+                column: c2,
+            },
+        };
+        let a = t[0].spans.as_deref().unwrap();
+        assert_eq!(a.key, vec![span(1, 3, 1, 4), span(6, 3, 6, 4)]);
+        assert_eq!(
+            a.text,
+            vec![span(1, 5, 1, 7), span(2, 2, 2, 3), span(6, 5, 6, 6)]
+        );
+        assert_eq!(t[0].value[0].spans.as_ref().unwrap().text, vec![]);
+        assert_eq!(t[1].spans.as_ref().unwrap().text, vec![span(4, 5, 5, 3)]);
+        assert!(try_chars_to_target(s.chars()).unwrap()[0].spans.is_none());
+        assert_eq!(t, try_chars_to_target(s.chars()).unwrap());
+    }
+
+    #[test]
+    fn test_parse_ordered() {
+        let s = "0~a:1
++2
+1~c:x
+0~b:3
+0~a:4
+1~c:y
+";
+        let t = try_chars_to_target(s.chars()).unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].text, vec!["1", "2", "4"]);
+        assert_eq!(t[0].value.len(), 2);
+
+        let mut options = ParseOptions::new();
+        options.ordered = true;
+        let t = chars_to_document_with(s.chars(), &options).unwrap().targets;
+        let names: Vec<&str> = t.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "a"]);
+        assert_eq!(t[0].text, vec!["1", "2"]);
+        assert_eq!(t[0].value[0].text, vec!["x"]);
+        assert_eq!(t[2].text, vec!["4"]);
+        assert_eq!(t[2].value[0].text, vec!["y"]);
+    }
 }
@@ -0,0 +1,230 @@
+use objnor::html::{Converter, DocumentOptions};
+use objnor::json::{json_to_target, vec_to_json};
+use objnor::{
+    fmt_with, from_reader_with, vec_to_map, vec_to_text, vec_to_xml_with, Document, FmtOptions,
+    OutputOptions, ParseOptions, ReadError,
+};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: objnor <command> [options] [file]
+
+commands:
+  check [file...]                 report parse errors as file:row:col
+  fmt [--sort] [file]             rewrite in canonical form, --sort sorts sibling keys
+  to-xml [file]                   convert to xml
+  to-html [file]                  convert to html
+  to-json [file]                  convert to json
+  from-json [file]                convert json to text format
+  flatten [-s separator] [file]   print key=value lines, keys joined by separator (default \".\")
+
+options:
+  --ordered      keep every occurrence of a key in source order
+  --strict       stop at the first error
+  --indent <n>   indent xml and html output by n spaces per level
+  --document     to-html: full document with doctype, head and body
+
+Without file, or with \"-\", read from stdin.";
+
+struct Args {
+    command: String,
+    ordered: bool,
+    strict: bool,
+    separator: String,
+    output: OutputOptions,
+    document: bool,
+    sort: bool,
+    files: Vec<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut iter = std::env::args().skip(1);
+    let command = iter.next().ok_or("missing command")?;
+    let mut args = Args {
+        command,
+        ordered: false,
+        strict: false,
+        separator: ".".to_string(),
+        output: OutputOptions::new(),
+        document: false,
+        sort: false,
+        files: Vec::new(),
+    };
+    while let Some(a) = iter.next() {
+        match a.as_str() {
+            "--ordered" => args.ordered = true,
+            "--strict" => args.strict = true,
+            "-s" | "--separator" => {
+                args.separator = iter.next().ok_or("missing separator")?;
+            }
+            "--document" => args.document = true,
+            "--sort" => args.sort = true,
+            "--indent" => {
+                let n = iter.next().ok_or("missing indent")?;
+                let n = n.parse().map_err(|_| format!("invalid indent {}", n))?;
+                args.output.indent = Some(n);
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if a.starts_with('-') && a != "-" => return Err(format!("unknown option {}", a)),
+            _ => args.files.push(a),
+        }
+    }
+    if args.command != "check" && args.files.len() > 1 {
+        return Err(format!("{} accepts one file", args.command));
+    }
+    Ok(args)
+}
+
+fn read(file: &str) -> io::Result<String> {
+    if file == "-" {
+        let mut s = String::new();
+        io::stdin().read_to_string(&mut s)?;
+        Ok(s)
+    } else {
+        std::fs::read_to_string(file)
+    }
+}
+
+fn name(file: &str) -> &str {
+    if file == "-" {
+        "<stdin>"
+    } else {
+        file
+    }
+}
+
+//parse or print errors
+fn parse(file: &str, options: &ParseOptions) -> Result<Document, ()> {
+    let rst = if file == "-" {
+        from_reader_with(io::stdin().lock(), options)
+    } else {
+        File::open(file)
+            .map_err(ReadError::Io)
+            .and_then(|f| from_reader_with(f, options))
+    };
+    rst.map_err(|e| match e {
+        ReadError::Io(e) => eprintln!("{}: {}", name(file), e),
+        ReadError::Parse(errors) => {
+            for e in errors {
+                eprintln!("{}:{}:{}: {}", name(file), e.row, e.column, e.kind);
+            }
+        }
+    })
+}
+
+fn check(args: &Args, options: &ParseOptions) -> ExitCode {
+    let mut code = ExitCode::SUCCESS;
+    let files = if args.files.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        args.files.clone()
+    };
+    for f in &files {
+        if parse(f, options).is_err() {
+            code = ExitCode::FAILURE;
+        }
+    }
+    code
+}
+
+fn convert(args: &Args, options: &ParseOptions) -> Result<String, ()> {
+    let file = args.files.first().map(|s| s.as_str()).unwrap_or("-");
+    let d = parse(file, options)?;
+    let s = match args.command.as_str() {
+        "to-xml" => vec_to_xml_with(d.targets, &args.output),
+        "to-html" if args.document => {
+            let mut options = DocumentOptions::new();
+            options.lift_head = true;
+            options.output = args.output.clone();
+            Converter::new().convert_document(d.targets, &options)
+        }
+        "to-html" => {
+            let c = Converter::new();
+            d.targets
+                .into_iter()
+                .map(|t| c.convert_with(t, &args.output))
+                .collect()
+        }
+        "to-json" => vec_to_json(&d.targets),
+        "flatten" => {
+            let mut map: Vec<(String, Vec<String>)> =
+                vec_to_map(d.targets, &args.separator).into_iter().collect();
+            map.sort();
+            let mut s = String::new();
+            for (k, v) in map {
+                for t in v {
+                    s.push_str(&k);
+                    s.push('=');
+                    s.push_str(&t.replace('\\', "\\\\").replace('\n', "\\n"));
+                    s.push('\n');
+                }
+            }
+            s
+        }
+        _ => unreachable!(),
+    };
+    Ok(s)
+}
+
+fn format(args: &Args, options: &ParseOptions) -> Result<String, ()> {
+    let file = args.files.first().map(|s| s.as_str()).unwrap_or("-");
+    let s = read(file).map_err(|e| eprintln!("{}: {}", name(file), e))?;
+    let mut fmt_options = FmtOptions::new();
+    fmt_options.sort_keys = args.sort;
+    fmt_options.parse = options.clone();
+    fmt_with(&s, &fmt_options).map_err(|errors| {
+        for e in errors {
+            eprintln!("{}:{}:{}: {}", name(file), e.row, e.column, e.kind);
+        }
+    })
+}
+
+fn from_json(args: &Args) -> Result<String, ()> {
+    let file = args.files.first().map(|s| s.as_str()).unwrap_or("-");
+    let s = read(file).map_err(|e| eprintln!("{}: {}", name(file), e))?;
+    let t = json_to_target(&s)
+        .map_err(|e| eprintln!("{}:{}:{}: {}", name(file), e.row, e.column, e.message))?;
+    vec_to_text(&t).map_err(|e| eprintln!("{}", e))
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{}", e);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let mut options = ParseOptions::new();
+    options.ordered = args.ordered;
+    options.strict = args.strict;
+    let rst = match args.command.as_str() {
+        "check" => return check(&args, &options),
+        "from-json" => from_json(&args),
+        "fmt" => format(&args, &options),
+        "to-xml" | "to-html" | "to-json" | "flatten" => convert(&args, &options),
+        c => {
+            eprintln!("unknown command {}\n{}", c, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match rst {
+        Ok(mut s) => {
+            if !s.ends_with('\n') {
+                s.push('\n');
+            }
+            match io::stdout().write_all(s.as_bytes()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Err(()) => ExitCode::FAILURE,
+    }
+}
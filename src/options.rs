@@ -0,0 +1,143 @@
+///Options for parsing text format to `Document`.
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    ///Keep every occurrence of a key as its own `Target` in source order,
+    ///instead of grouping siblings with the same key into one `Target`.
+    pub ordered: bool,
+    ///Record source ranges of keys and texts in `Target.spans`.
+    pub spans: bool,
+    ///Stop at the first error instead of skipping the wrong line, so that the result is
+    ///either the whole document or exactly one error.
+    pub strict: bool,
+    pub limits: Limits,
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+//default max_depth, also the nesting limit of the json, xml and html parsers
+pub(crate) const MAX_DEPTH: usize = 1024;
+
+///Limits for untrusted input. `None` is no limit, which is the default except for `max_depth`.
+///
+///Exceeding a limit stops the parser with one error, also without `ParseOptions.strict`.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    ///Levels of nesting, offset 0 is level 1. The default is 1024.
+    ///
+    ///`Target` is dropped, cloned, compared and deserialized recursively, so with `None`
+    ///very deep input can overflow the stack.
+    pub max_depth: Option<usize>,
+    ///Chars in a key.
+    pub max_key_len: Option<usize>,
+    ///Chars in a text of an entry, line breaks included, and in a header line.
+    pub max_text_len: Option<usize>,
+    ///Texts in an entry, the first text and one more per plus(+).
+    pub max_array_items: Option<usize>,
+    ///Entries in the document.
+    pub max_nodes: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: Some(MAX_DEPTH),
+            max_key_len: None,
+            max_text_len: None,
+            max_array_items: None,
+            max_nodes: None,
+        }
+    }
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Limits for input from untrusted sources.
+    pub fn untrusted() -> Self {
+        Limits {
+            max_depth: Some(128),
+            max_key_len: Some(1024),
+            max_text_len: Some(1 << 20),
+            max_array_items: Some(10_000),
+            max_nodes: Some(1_000_000),
+        }
+    }
+}
+
+///Line ending of pretty output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+///Options for xml and html output. The default is compact output on one line.
+#[derive(Clone, Debug, Default)]
+pub struct OutputOptions {
+    ///Spaces per level. With `Some`, every element starts on its own line.
+    pub indent: Option<usize>,
+    pub line_ending: LineEnding,
+    ///Text longer than this many chars goes on its own line between the tags, which adds
+    ///whitespace to the text. With `None`, text always stays next to its tags.
+    pub inline_text: Option<usize>,
+    ///Put every attribute on its own line when a start tag has more attributes than this.
+    pub wrap_attributes: Option<usize>,
+}
+
+impl OutputOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Indented output with the indent width.
+    pub fn pretty(indent: usize) -> Self {
+        OutputOptions {
+            indent: Some(indent),
+            ..Self::default()
+        }
+    }
+}
+
+///Options for `fmt_with`.
+#[derive(Clone, Debug)]
+pub struct FmtOptions {
+    ///Sort siblings by key, keeping the order of siblings with the same key.
+    pub sort_keys: bool,
+    ///Remove spaces and tabs at the end of header and text lines, which changes texts that end
+    ///with them. Keys are kept as they are.
+    pub trim_trailing_whitespace: bool,
+    ///Options for parsing, `ordered` is always on.
+    pub parse: ParseOptions,
+}
+
+impl Default for FmtOptions {
+    fn default() -> Self {
+        FmtOptions {
+            sort_keys: false,
+            trim_trailing_whitespace: true,
+            parse: ParseOptions::default(),
+        }
+    }
+}
+
+impl FmtOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
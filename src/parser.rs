@@ -1,5 +1,6 @@
 use crate::contents::Contents;
-use crate::header::Header;
+use crate::error::{ParseError, ParseErrorKind};
+use crate::options::{Limits, ParseOptions};
 
 //'\r'
 const CR: char = '\r';
@@ -19,8 +20,8 @@ const PLUS: char = '+';
 const SPACE: char = ' ';
 //'\'
 const BACKSLASH: char = '\\';
-//0
-pub(crate) const END: char = 0 as char;
+//0, end of input, a NUL char in input is rejected
+const END: char = 0 as char;
 
 fn is_crlf(c: char) -> bool {
     c == CR || c == LF
@@ -29,10 +30,18 @@ fn is_crlf(c: char) -> bool {
 pub(crate) struct Parser<T: Contents> {
     n: usize,
     row: usize,
+    previous: char,
     current_function: fn(&mut Parser<T>, char),
     new_line: NewLine,
     header_parser: HeaderParser,
     offset: Offset,
+    strict: bool,
+    stopped: bool,
+    limits: Limits,
+    key_len: usize,
+    text_len: usize,
+    items: usize,
+    nodes: usize,
     contents: T,
 }
 
@@ -41,10 +50,18 @@ impl<T: Contents> Parser<T> {
         Parser {
             n: 0,
             row: 1,
+            previous: END,
             current_function: Self::accept_pre_header,
             new_line: NewLine::new(),
             header_parser: HeaderParser::new(),
             offset: Offset::new(),
+            strict: false,
+            stopped: false,
+            limits: Limits::default(),
+            key_len: 0,
+            text_len: 0,
+            items: 0,
+            nodes: 0,
             contents,
         }
     }
@@ -52,6 +69,7 @@ impl<T: Contents> Parser<T> {
     fn reset(&mut self) {
         self.n = 0;
         self.row = 1;
+        self.previous = END;
         self.current_function = Self::accept_pre_header;
         self.new_line = NewLine::new();
         self.header_parser = HeaderParser::new();
@@ -62,18 +80,82 @@ impl<T: Contents> Parser<T> {
         self.contents
     }
 
+    pub(crate) fn contents_mut(&mut self) -> &mut T {
+        &mut self.contents
+    }
+
+    //strict stops at the first error, limits stop at the first limit error
+    pub(crate) fn set_options(&mut self, options: &ParseOptions) {
+        self.strict = options.strict;
+        self.limits = options.limits.clone();
+    }
+
+    pub(crate) fn stopped(&self) -> bool {
+        self.stopped
+    }
+
     pub(crate) fn accept(&mut self, c: char) {
-        self.n += 1;
+        if self.stopped {
+            return;
+        }
+        if c == END {
+            self.count(c);
+            self.report(ParseError {
+                kind: ParseErrorKind::NulChar,
+                row: self.row,
+                column: self.n,
+                char: Some(c),
+                state: "accept",
+            });
+            return;
+        }
+        self.count(c);
+        self.contents.position(self.row, self.n);
         (self.current_function)(self, c);
     }
 
+    //invalid UTF-8 sequence, read as U+FFFD
+    pub(crate) fn accept_invalid(&mut self) {
+        if self.stopped {
+            return;
+        }
+        let c = char::REPLACEMENT_CHARACTER;
+        self.count(c);
+        self.contents.position(self.row, self.n);
+        self.error(ParseErrorKind::InvalidUtf8, c, "accept_invalid");
+        if self.stopped {
+            return;
+        }
+        (self.current_function)(self, c);
+    }
+
+    //end of input, the parser is ready for the next input
+    pub(crate) fn finish(&mut self) {
+        if self.stopped {
+            return;
+        }
+        self.count(END);
+        self.contents.position(self.row, self.n);
+        (self.current_function)(self, END);
+    }
+
+    fn count(&mut self, c: char) {
+        if self.previous == LF || (self.previous == CR && c != LF) {
+            self.row += 1;
+            self.n = 0;
+        }
+        self.n += 1;
+        self.previous = c;
+    }
+
     fn accept_pre_header(&mut self, c: char) {
         self.header_parser.pre();
+        self.text_len = 0;
         if c == NUMBERSIGN {
             self.current_function = Self::accept_header;
             return;
         } else if is_crlf(c) {
-            self.error();
+            self.error(ParseErrorKind::UnexpectedNewLine, c, "accept_pre_header");
             return;
         }
         self.current_function = Self::accept_pre_offset;
@@ -81,25 +163,31 @@ impl<T: Contents> Parser<T> {
     }
 
     fn accept_header(&mut self, c: char) {
-        if c == NUMBERSIGN {
-            return;
-        } else if is_crlf(c) {
-            if !self.header_parser.post() {
-                self.error();
-            }
+        if is_crlf(c) {
+            self.contents.header(self.header_parser.post());
             self.current_function = Self::header_new_line;
             self.header_new_line(c);
             return;
+        } else if c == END {
+            self.contents.header(self.header_parser.post());
+            self.reset();
+            return;
+        }
+        self.text_len += 1;
+        if self.exceeds(
+            ParseErrorKind::TextTooLong,
+            self.text_len,
+            self.limits.max_text_len,
+            c,
+            "accept_header",
+        ) {
+            return;
         }
         self.header_parser.accept(c);
     }
 
     fn header_new_line(&mut self, c: char) {
-        let s = self.new_line.accept(c);
-        if s.len() > 0 {
-            self.row += 1;
-            self.n = 0;
-        }
+        self.new_line.accept(c);
         if is_crlf(c) {
             return;
         }
@@ -121,7 +209,7 @@ impl<T: Contents> Parser<T> {
             self.reset();
             return;
         }
-        self.error();
+        self.error(ParseErrorKind::InvalidOffset, c, "accept_pre_offset");
         self.current_function = Self::offset_error;
     }
 
@@ -130,12 +218,28 @@ impl<T: Contents> Parser<T> {
             self.offset.accept(c);
             return;
         } else if c == TILDE {
-            if self.offset.post() {
-                self.current_function = Self::accept_pre_key;
-                return;
+            match self.offset.post() {
+                Ok(()) => {
+                    let depth = self.offset.number + 1;
+                    if self.exceeds(
+                        ParseErrorKind::TooDeep,
+                        depth,
+                        self.limits.max_depth,
+                        c,
+                        "accept_offset",
+                    ) {
+                        return;
+                    }
+                    self.current_function = Self::accept_pre_key;
+                }
+                Err(kind) => {
+                    self.error(kind, c, "accept_offset");
+                    self.current_function = Self::offset_error;
+                }
             }
+            return;
         } else if is_crlf(c) {
-            self.error();
+            self.error(ParseErrorKind::MissingTilde, c, "accept_offset");
             self.current_function = Self::offset_new_line;
             self.offset_new_line(c);
             return;
@@ -143,23 +247,19 @@ impl<T: Contents> Parser<T> {
             self.reset();
             return;
         }
-        self.error();
+        self.error(ParseErrorKind::InvalidOffset, c, "accept_offset");
         self.current_function = Self::offset_error;
     }
 
     fn offset_error(&mut self, c: char) {
-        if is_crlf(c) {
+        if is_crlf(c) || c == END {
             self.current_function = Self::offset_new_line;
             self.offset_new_line(c);
         }
     }
 
     fn offset_new_line(&mut self, c: char) {
-        let s = self.new_line.accept(c);
-        if s.len() > 0 {
-            self.row += 1;
-            self.n = 0;
-        }
+        self.new_line.accept(c);
         if is_crlf(c) {
             return;
         }
@@ -168,13 +268,24 @@ impl<T: Contents> Parser<T> {
     }
 
     fn accept_pre_key(&mut self, c: char) {
+        self.nodes += 1;
+        if self.exceeds(
+            ParseErrorKind::TooManyNodes,
+            self.nodes,
+            self.limits.max_nodes,
+            c,
+            "accept_pre_key",
+        ) {
+            return;
+        }
+        self.key_len = 0;
         self.contents.pre_key(self.offset.number);
         if c == COLON {
-            self.error();
+            self.error(ParseErrorKind::EmptyKey, c, "accept_pre_key");
             self.current_function = Self::key_error;
             return;
-        } else if is_crlf(c) {
-            self.error();
+        } else if is_crlf(c) || c == END {
+            self.error(ParseErrorKind::EmptyKey, c, "accept_pre_key");
             self.current_function = Self::offset_new_line;
             self.offset_new_line(c);
             return;
@@ -203,27 +314,43 @@ impl<T: Contents> Parser<T> {
             self.reset();
             return;
         }
-        self.contents.key(c);
+        self.key_char(c, "accept_key");
     }
 
     fn key_backslash(&mut self, c: char) {
-        if is_crlf(c) {
+        if is_crlf(c) || c == END {
             self.current_function = Self::accept_key;
             self.accept_key(c);
             return;
         }
-        self.contents.key(c);
         self.current_function = Self::accept_key;
+        self.key_char(c, "key_backslash");
+    }
+
+    fn key_char(&mut self, c: char, state: &'static str) {
+        self.key_len += 1;
+        if self.exceeds(
+            ParseErrorKind::KeyTooLong,
+            self.key_len,
+            self.limits.max_key_len,
+            c,
+            state,
+        ) {
+            return;
+        }
+        self.contents.key(c);
     }
 
     fn key_error(&mut self, c: char) {
-        if is_crlf(c) {
+        if is_crlf(c) || c == END {
             self.current_function = Self::accept_pre_offset;
             self.accept_pre_offset(c);
         }
     }
 
     fn accept_pre_text(&mut self, c: char) {
+        self.text_len = 0;
+        self.items = 1;
         self.contents.pre_text();
         if is_crlf(c) {
             self.current_function = Self::text_new_line;
@@ -243,6 +370,16 @@ impl<T: Contents> Parser<T> {
             self.text_new_line(c);
             return;
         }
+        self.text_len += 1;
+        if self.exceeds(
+            ParseErrorKind::TextTooLong,
+            self.text_len,
+            self.limits.max_text_len,
+            c,
+            "accept_text",
+        ) {
+            return;
+        }
         self.contents.text(c);
     }
 
@@ -251,16 +388,23 @@ impl<T: Contents> Parser<T> {
             return;
         }
         let s = self.new_line.accept(c);
-        if s.len() > 0 {
-            self.row += 1;
-            self.n = 0;
-        } else if is_crlf(c) {
+        if s.is_empty() && is_crlf(c) {
             return;
         }
-        if s.len() > 0 && c == VERTICAL {
+        if !s.is_empty() && c == VERTICAL {
+            self.text_len += s.len();
             for i in s {
                 self.contents.text(*i);
             }
+            if self.exceeds(
+                ParseErrorKind::TextTooLong,
+                self.text_len,
+                self.limits.max_text_len,
+                c,
+                "text_new_line",
+            ) {
+                return;
+            }
         }
         if c == VERTICAL || c == PLUS {
             self.current_function = Self::text_more;
@@ -281,11 +425,22 @@ impl<T: Contents> Parser<T> {
             self.current_function = Self::accept_text;
             return;
         } else if c == PLUS {
+            self.items += 1;
+            if self.exceeds(
+                ParseErrorKind::TooManyItems,
+                self.items,
+                self.limits.max_array_items,
+                c,
+                "text_more",
+            ) {
+                return;
+            }
+            self.text_len = 0;
             self.contents.text_array();
             self.current_function = Self::accept_text;
             return;
         }
-        self.error();
+        self.error(ParseErrorKind::InvalidContinuation, c, "text_more");
         if is_crlf(c) {
             self.current_function = Self::text_new_line;
             self.text_new_line(c);
@@ -295,14 +450,45 @@ impl<T: Contents> Parser<T> {
     }
 
     fn text_more_error(&mut self, c: char) {
-        if is_crlf(c) {
+        if is_crlf(c) || c == END {
             self.current_function = Self::text_new_line;
             self.text_new_line(c);
         }
     }
 
-    fn error(&mut self) {
-        self.contents.error(self.row, self.n);
+    fn error(&mut self, kind: ParseErrorKind, c: char, state: &'static str) {
+        self.report(ParseError {
+            kind,
+            row: self.row,
+            column: self.n,
+            char: if c == END { None } else { Some(c) },
+            state,
+        });
+    }
+
+    //a limit error always stops the parser, the rest of input is not read
+    fn exceeds(
+        &mut self,
+        kind: ParseErrorKind,
+        n: usize,
+        limit: Option<usize>,
+        c: char,
+        state: &'static str,
+    ) -> bool {
+        if limit.is_none_or(|m| n <= m) {
+            return false;
+        }
+        self.error(kind, c, state);
+        self.stopped = true;
+        true
+    }
+
+    fn report(&mut self, error: ParseError) {
+        if self.stopped {
+            return;
+        }
+        self.contents.error(error);
+        self.stopped = self.strict;
     }
 }
 
@@ -364,16 +550,12 @@ impl NewLine {
 }
 
 struct HeaderParser {
-    header: Header,
     str: String,
 }
 
 impl HeaderParser {
     fn new() -> Self {
-        HeaderParser {
-            header: Header::new(),
-            str: String::new(),
-        }
+        HeaderParser { str: String::new() }
     }
 
     fn pre(&mut self) {
@@ -384,10 +566,8 @@ impl HeaderParser {
         self.str.push(c);
     }
 
-    fn post(&mut self) -> bool {
-        self.header.accept(self.str.clone());
-        self.str.clear();
-        true
+    fn post(&self) -> &str {
+        &self.str
     }
 }
 
@@ -410,24 +590,29 @@ impl Offset {
         self.str.clear();
     }
 
+    //leading zeros are dropped, and digits past what usize holds are not kept, post fails anyway
     fn accept(&mut self, c: char) {
-        self.str.push(c);
+        if self.str == "0" {
+            self.str.clear();
+        }
+        if self.str.len() <= usize::BITS as usize / 4 {
+            self.str.push(c);
+        }
     }
 
-    fn post(&mut self) -> bool {
+    fn post(&mut self) -> Result<(), ParseErrorKind> {
         match usize::from_str_radix(&self.str, 16) {
             Ok(n) => {
                 self.number = n;
             }
-            Err(e) => {
-                println!("{:?}", e);
-                return false;
+            Err(_) => {
+                return Err(ParseErrorKind::InvalidOffset);
             }
         }
         if self.number > self.available {
-            return false;
+            return Err(ParseErrorKind::OffsetJump);
         }
         self.available = self.number + 1;
-        true
+        Ok(())
     }
 }
@@ -0,0 +1,674 @@
+use crate::de::Error;
+use crate::{vec_to_text, Target};
+use serde::ser::{self, Impossible, Serialize};
+use std::fmt;
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error {
+            message: msg.to_string(),
+            position: None,
+        }
+    }
+}
+
+fn error(msg: &str) -> Error {
+    ser::Error::custom(msg)
+}
+
+///Convert any `Serialize` type to text format.
+///
+///The value must be a struct or a map. Nested structs and maps get a greater offset,
+///sequences of scalars become plus(+) arrays, sequences of structs repeat the key,
+///`None` fields are left out.
+///
+///Fails for values that would not come back the same: `None` or an empty string in a sequence,
+///and `Some` of an empty string.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    match value.serialize(ValueSerializer)? {
+        Out::Map(targets) => vec_to_text(&targets).map_err(|e| error(&e.to_string())),
+        _ => Err(error("top level must be a struct or a map")),
+    }
+}
+
+//serialized value, before it gets a key
+enum Out {
+    Text(String),
+    Empty,
+    Skip,
+    Seq(Vec<Out>),
+    Map(Vec<Target>),
+}
+
+fn push(children: &mut Vec<Target>, name: String, out: Out) -> Result<(), Error> {
+    let mut target = Target::new(name);
+    match out {
+        Out::Text(s) => target.text.push(s),
+        Out::Empty => {}
+        Out::Skip => return Ok(()),
+        Out::Map(value) => target.value = value,
+        Out::Seq(items) => {
+            //the parser drops empty texts and a skipped item leaves no trace
+            for i in &items {
+                match i {
+                    Out::Skip => return Err(error("None in a sequence is not supported")),
+                    Out::Text(s) if s.is_empty() => {
+                        return Err(error("empty string in a sequence is not supported"))
+                    }
+                    _ => {}
+                }
+            }
+            if items.iter().all(|i| matches!(i, Out::Text(_))) {
+                for i in items {
+                    if let Out::Text(s) = i {
+                        target.text.push(s);
+                    }
+                }
+            } else {
+                for i in items {
+                    if let Out::Seq(_) = i {
+                        return Err(error("nested sequences are not supported"));
+                    }
+                    push(children, target.name.clone(), i)?;
+                }
+                return Ok(());
+            }
+        }
+    }
+    children.push(target);
+    Ok(())
+}
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Out;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Out, Error> {
+        Ok(Out::Text(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Out, Error> {
+        Ok(Out::Seq(
+            v.iter().map(|b| Out::Text(b.to_string())).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Out, Error> {
+        Ok(Out::Skip)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Out, Error> {
+        //a key without text is read back as None
+        match value.serialize(self)? {
+            Out::Text(s) if s.is_empty() => Err(error("Some of an empty string is not supported")),
+            out => Ok(out),
+        }
+    }
+
+    fn serialize_unit(self) -> Result<Out, Error> {
+        Ok(Out::Empty)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Out, Error> {
+        Ok(Out::Empty)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Out, Error> {
+        Ok(Out::Text(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Out, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Out, Error> {
+        let mut children = Vec::new();
+        push(&mut children, variant.to_string(), value.serialize(self)?)?;
+        Ok(Out::Map(children))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            variant: None,
+            children: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            variant: Some(variant),
+            children: Vec::new(),
+            key: None,
+        })
+    }
+}
+
+//wrap in a child named by the enum variant
+fn variant(variant: Option<&'static str>, out: Out) -> Result<Out, Error> {
+    match variant {
+        Some(v) => {
+            let mut children = Vec::new();
+            push(&mut children, v.to_string(), out)?;
+            Ok(Out::Map(children))
+        }
+        None => Ok(out),
+    }
+}
+
+struct SeqSerializer {
+    variant: Option<&'static str>,
+    items: Vec<Out>,
+}
+
+impl SeqSerializer {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Out, Error> {
+        variant(self.variant, Out::Seq(self.items))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Out;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Out, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Out;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Out, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Out;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Out, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Out;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<Out, Error> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    variant: Option<&'static str>,
+    children: Vec<Target>,
+    key: Option<String>,
+}
+
+impl MapSerializer {
+    fn field<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        let out = value.serialize(ValueSerializer)?;
+        push(&mut self.children, key, out)
+    }
+
+    fn finish(self) -> Result<Out, Error> {
+        variant(self.variant, Out::Map(self.children))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Out;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().ok_or_else(|| error("value without key"))?;
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Out, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Out;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Out, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Out;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Out, Error> {
+        self.finish()
+    }
+}
+
+//map keys must be scalars
+struct KeySerializer;
+
+fn key_error() -> Error {
+    error("map key must be a string, a number, a bool or a char")
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(key_error())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(key_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_str;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Mode {
+        Fast,
+        Limit { rate: u32 },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Server {
+        host: String,
+        port: u16,
+        tls: Option<bool>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct State {
+        name: String,
+        ratio: f64,
+        tags: Vec<String>,
+        servers: Vec<Server>,
+        mode: Mode,
+        other: Mode,
+        env: BTreeMap<String, u8>,
+    }
+
+    #[test]
+    fn test_to_string() {
+        let state = State {
+            name: "a\nb".to_string(),
+            ratio: 0.5,
+            tags: vec!["x".to_string(), "y:z".to_string()],
+            servers: vec![
+                Server {
+                    host: "h1".to_string(),
+                    port: 80,
+                    tls: Some(true),
+                },
+                Server {
+                    host: "h2".to_string(),
+                    port: 81,
+                    tls: None,
+                },
+            ],
+            mode: Mode::Fast,
+            other: Mode::Limit { rate: 3 },
+            env: BTreeMap::from([("a:b".to_string(), 1)]),
+        };
+        let s = to_string(&state).unwrap();
+        assert_eq!(
+            s,
+            "0~name:a
+|b
+0~ratio:0.5
+0~tags:x
++y:z
+0~servers
+1~host:h1
+1~port:80
+1~tls:true
+0~servers
+1~host:h2
+1~port:81
+0~mode:Fast
+0~other
+1~Limit
+2~rate:3
+0~env
+1~a\\:b:1
+"
+        );
+        assert_eq!(from_str::<State>(&s).unwrap(), state);
+        assert!(to_string(&1).is_err());
+
+        //values that would not round trip
+        let e = to_string(&BTreeMap::from([("a", vec!["", "a"])])).unwrap_err();
+        assert_eq!(e.message, "empty string in a sequence is not supported");
+        let e = to_string(&BTreeMap::from([("a", Some(""))])).unwrap_err();
+        assert_eq!(e.message, "Some of an empty string is not supported");
+        let e = to_string(&BTreeMap::from([("a", vec![Some(1), None, Some(2)])])).unwrap_err();
+        assert_eq!(e.message, "None in a sequence is not supported");
+        let s = to_string(&BTreeMap::from([("a", "")])).unwrap();
+        assert_eq!(from_str::<BTreeMap<String, String>>(&s).unwrap()["a"], "");
+    }
+}
@@ -0,0 +1,24 @@
+///Position in the source, as in `ParseError`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    ///Row number, starting at 1.
+    pub row: usize,
+    ///Column number in chars, starting at 1.
+    pub column: usize,
+}
+
+///Source range, `end` is just after the last char.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+///Source ranges of a `Target`, recorded with `ParseOptions.spans`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Spans {
+    ///Key of every occurrence, siblings with the same key are grouped into one `Target`.
+    pub key: Vec<Span>,
+    ///Every text, in the order of `Target.text`.
+    pub text: Vec<Span>,
+}
@@ -0,0 +1,161 @@
+use crate::contents::Contents;
+use crate::parser::Parser;
+use crate::utf8::Utf8Decoder;
+
+///Push parser for input that comes in chunks, e.g. from a socket or a pipe.
+///
+///Chunks may split a line anywhere. `Contents` callbacks fire while chunks are fed,
+///an entry ends with `post_text` when the next entry starts or at `finish`,
+///because continuation lines may follow it. Memory use is up to the `Contents`.
+///
+///```
+///use objnor::{Contents, ParseError, StreamParser};
+///
+///#[derive(Default)]
+///struct Count(usize);
+///
+///impl Contents for Count {
+///    type Item = usize;
+///    fn get(self) -> usize { self.0 }
+///    fn pre_key(&mut self, _offset: usize) {}
+///    fn key(&mut self, _c: char) {}
+///    fn post_key(&mut self) {}
+///    fn pre_text(&mut self) {}
+///    fn text(&mut self, _c: char) {}
+///    fn text_array(&mut self) {}
+///    fn post_text(&mut self) { self.0 += 1; }
+///    fn error(&mut self, _error: ParseError) {}
+///}
+///
+///let mut p = StreamParser::new(Count::default());
+///p.feed("0~a:x\n0~");
+///p.feed("b:y\n");
+///assert_eq!(p.finish(), 2);
+///```
+pub struct StreamParser<T: Contents> {
+    parser: Parser<T>,
+    decoder: Utf8Decoder,
+}
+
+impl<T: Contents> StreamParser<T> {
+    pub fn new(contents: T) -> Self {
+        StreamParser {
+            parser: Parser::new(contents),
+            decoder: Utf8Decoder::new(),
+        }
+    }
+
+    ///Accept next chunk. A NUL char is reported as `ParseErrorKind::NulChar` and skipped.
+    pub fn feed(&mut self, s: &str) {
+        for c in s.chars() {
+            self.parser.accept(c);
+        }
+    }
+
+    ///Accept next chunk of UTF-8 bytes, a char may be split between chunks. A leading BOM is skipped,
+    ///invalid bytes are reported as `ParseErrorKind::InvalidUtf8` and read as U+FFFD.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        let parser = &mut self.parser;
+        self.decoder.decode(bytes, &mut |c| match c {
+            Some(c) => parser.accept(c),
+            None => parser.accept_invalid(),
+        });
+    }
+
+    ///Accept next char.
+    pub fn feed_char(&mut self, c: char) {
+        self.parser.accept(c);
+    }
+
+    ///Contents, e.g. to take entries that are done while the stream goes on.
+    pub fn contents_mut(&mut self) -> &mut T {
+        self.parser.contents_mut()
+    }
+
+    ///End of input, get result.
+    pub fn finish(mut self) -> T::Item {
+        let parser = &mut self.parser;
+        self.decoder.finish(&mut |c| match c {
+            Some(c) => parser.accept(c),
+            None => parser.accept_invalid(),
+        });
+        self.parser.finish();
+        self.parser.contents().get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chars_to_document, Contents, ParseError, ParseErrorKind};
+
+    //collects finished entries as "offset~key:texts"
+    #[derive(Default)]
+    struct Entries {
+        entries: Vec<String>,
+        entry: String,
+        errors: Vec<ParseError>,
+    }
+
+    impl Contents for Entries {
+        type Item = Vec<String>;
+
+        fn get(self) -> Self::Item {
+            self.entries
+        }
+
+        fn pre_key(&mut self, offset: usize) {
+            self.entry = format!("{}~", offset);
+        }
+
+        fn key(&mut self, c: char) {
+            self.entry.push(c);
+        }
+
+        fn post_key(&mut self) {
+            self.entry.push(':');
+        }
+
+        fn pre_text(&mut self) {}
+
+        fn text(&mut self, c: char) {
+            self.entry.push(c);
+        }
+
+        fn text_array(&mut self) {
+            self.entry.push('+');
+        }
+
+        fn post_text(&mut self) {
+            self.entries.push(std::mem::take(&mut self.entry));
+        }
+
+        fn error(&mut self, error: ParseError) {
+            self.errors.push(error);
+        }
+    }
+
+    #[test]
+    fn test_feed() {
+        let s = "#h\r\n0~a:x\r\n|y\r\n+z\n1~b\\:c:\n0~d";
+        let expected = vec!["0~a:x\r\ny+z", "1~b:c:", "0~d:"];
+        //every split point
+        for i in 0..=s.len() {
+            let mut p = StreamParser::new(Entries::default());
+            p.feed(&s[..i]);
+            p.feed(&s[i..]);
+            assert_eq!(p.finish(), expected);
+        }
+
+        let mut p = StreamParser::new(Entries::default());
+        p.feed("0~a:x\n0~b");
+        assert_eq!(p.contents_mut().entries, vec!["0~a:x"]);
+        p.feed(":\0y\n");
+        assert_eq!(p.contents_mut().errors[0].kind, ParseErrorKind::NulChar);
+        assert_eq!(p.contents_mut().errors[0].column, 5);
+        assert_eq!(p.finish(), vec!["0~a:x", "0~b:y"]);
+
+        let e = chars_to_document("0~a\n0~".chars()).unwrap_err();
+        assert_eq!(e[0].kind, ParseErrorKind::EmptyKey);
+    }
+}
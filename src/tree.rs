@@ -1,105 +1,273 @@
-use crate::Target;
+use crate::{Span, Spans, Target};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::take;
 
-pub(crate) struct Root {
-    nodes: Vec<(String, Vec<Node>)>,
-    last_node: Option<*mut Node>,
+//indexes of nodes with the same key, in insertion order. the key is the key of the first node
+type Buckets = Vec<Vec<usize>>;
+
+//object built from the tree
+pub(crate) trait Item<S>: Sized {
+    fn item(name: S, text: Vec<S>, value: Vec<Self>, spans: Option<Spans>) -> Self;
+}
+
+impl Item<String> for Target {
+    fn item(name: String, text: Vec<String>, value: Vec<Self>, spans: Option<Spans>) -> Self {
+        Target {
+            name,
+            text,
+            value,
+            spans: spans.map(Box::new),
+        }
+    }
 }
 
-impl Root {
-    pub(crate) fn new() -> Self {
+pub(crate) struct Root<S = String> {
+    arena: Vec<Node<S>>,
+    nodes: Buckets,
+    //bucket of the key under the parent, None is the top level. empty if ordered
+    buckets: HashMap<(Option<usize>, S), usize>,
+    last_node: Option<usize>,
+    ordered: bool,
+}
+
+impl<S: AsRef<str> + Default + Clone + Eq + Hash> Root<S> {
+    //if ordered, every node has its own bucket
+    pub(crate) fn new(ordered: bool) -> Self {
         Root {
+            arena: Vec::new(),
             nodes: Vec::new(),
+            buckets: HashMap::new(),
             last_node: None,
+            ordered,
         }
     }
 
-    pub(crate) fn add(&mut self, node: Node) {
-        if node.offset == 0 {
-            let k = node.key.to_string();
-            if let Some(n) = self.nodes.iter_mut().find(|n| n.0 == k) {
-                n.1.push(node);
-                self.last_node = n.1.last_mut().map(|n| n as *mut Node);
+    pub(crate) fn add(&mut self, mut node: Node<S>) {
+        let index = self.arena.len();
+        let mut parent = None;
+        if node.offset != 0 {
+            let Some(last) = self.last_node else {
+                return;
+            };
+            parent = if node.offset == self.arena[last].offset + 1 {
+                Some(last)
             } else {
-                self.nodes.push((k, vec![node]));
-                if let Some(n) = self.nodes.last_mut() {
-                    self.last_node = n.1.last_mut().map(|n| n as *mut Node);
-                }
+                self.get_parent_by_offset(last, node.offset)
+            };
+            if parent.is_none() {
+                return;
             }
+        }
+        node.parent = parent;
+        let buckets = match parent {
+            Some(p) => &mut self.arena[p].nodes,
+            None => &mut self.nodes,
+        };
+        if self.ordered {
+            buckets.push(vec![index]);
         } else {
-            unsafe {
-                if let Some(p) = self.last_node {
-                    if node.offset == (*p).offset + 1 {
-                        self.last_node = (*p).add(node);
-                    } else if let Some(p) = (*p).get_parent_by_offset(node.offset) {
-                        self.last_node = (*p).add(node);
-                    }
-                }
+            let len = buckets.len();
+            let b = *self
+                .buckets
+                .entry((parent, node.key.clone()))
+                .or_insert(len);
+            if b == len {
+                buckets.push(vec![index]);
+            } else {
+                buckets[b].push(index);
             }
         }
+        self.arena.push(node);
+        self.last_node = Some(index);
     }
 
-    pub(crate) fn build(&self) -> Vec<Target> {
-        build(&self.nodes)
+    //parent of the nearest ancestor-or-self that has the offset
+    fn get_parent_by_offset(&self, mut index: usize, seekoffset: usize) -> Option<usize> {
+        loop {
+            let node = &self.arena[index];
+            if node.offset == seekoffset {
+                return node.parent;
+            } else if seekoffset < node.offset {
+                index = node.parent?;
+            } else {
+                return None;
+            }
+        }
     }
-}
 
-fn build(nodes: &Vec<(String, Vec<Node>)>) -> Vec<Target> {
-    let mut rst = Vec::new();
-    for v in nodes {
-        let mut o = Target::new(v.0.to_string());
-        for n in &v.1 {
-            if n.text.len() > 0 {
-                o.text.push(n.text.clone());
+    //children are added after their parent, so building from the last node up
+    //finds the items of children ready, without recursion
+    pub(crate) fn build<T: Item<S>>(mut self) -> Vec<T> {
+        let mut built: Vec<Vec<T>> = self.arena.iter().map(|_| Vec::new()).collect();
+        for i in (0..self.arena.len()).rev() {
+            let nodes = take(&mut self.arena[i].nodes);
+            built[i] = self.build_buckets(nodes, &mut built);
+        }
+        let nodes = take(&mut self.nodes);
+        self.build_buckets(nodes, &mut built)
+    }
+
+    fn build_buckets<T: Item<S>>(&mut self, nodes: Buckets, built: &mut [Vec<T>]) -> Vec<T> {
+        let mut rst = Vec::new();
+        for v in nodes {
+            let name = take(&mut self.arena[v[0]].key);
+            let mut text = Vec::new();
+            let mut value = Vec::new();
+            let mut spans = self.arena[v[0]].spans.as_ref().map(|_| Spans::default());
+            for i in v {
+                let n = &mut self.arena[i];
+                let texts = take(&mut n.text);
+                match (n.spans.take(), &mut spans) {
+                    (Some((key, text_spans)), Some(spans)) => {
+                        spans.key.push(key);
+                        for (t, span) in texts.into_iter().zip(text_spans) {
+                            if !t.as_ref().is_empty() {
+                                text.push(t);
+                                spans.text.push(span);
+                            }
+                        }
+                    }
+                    _ => text.extend(texts.into_iter().filter(|t| !t.as_ref().is_empty())),
+                }
+                value.append(&mut built[i]);
             }
-            o.value.append(&mut build(&n.nodes));
+            rst.push(T::item(name, text, value, spans));
         }
-        rst.push(o);
+        rst
     }
-    rst
 }
 
 #[derive(Debug)]
-pub(crate) struct Node {
-    parent: Option<*mut Node>,
-    nodes: Vec<(String, Vec<Node>)>,
+pub(crate) struct Node<S = String> {
+    parent: Option<usize>,
+    nodes: Buckets,
     offset: usize,
-    key: String,
-    text: String,
+    key: S,
+    text: Vec<S>,
+    //key span and text spans
+    spans: Option<(Span, Vec<Span>)>,
 }
 
-impl Node {
-    pub(crate) fn new(offset: usize, key: String, text: String) -> Self {
+impl<S> Node<S> {
+    pub(crate) fn new(offset: usize, key: S, text: Vec<S>) -> Self {
         Node {
             parent: None,
             nodes: Vec::new(),
             offset,
             key,
             text,
+            spans: None,
         }
     }
 
-    fn add(&mut self, mut node: Node) -> Option<*mut Node> {
-        node.parent = Some(self);
-        let k = node.key.to_string();
-        if let Some(n) = self.nodes.iter_mut().find(|n| n.0 == k) {
-            n.1.push(node);
-            n.1.last_mut().map(|n| n as *mut Node)
-        } else {
-            self.nodes.push((k, vec![node]));
-            self.nodes.last_mut()?.1.last_mut().map(|n| n as *mut Node)
+    pub(crate) fn with_spans(mut self, key: Span, text: Vec<Span>) -> Self {
+        self.spans = Some((key, text));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chars_to_document_with, try_chars_to_target, vec_to_text, ParseOptions};
+
+    //linear congruential generator, enough to shuffle shapes
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, n: u64) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (self.0 >> 33) % n
         }
     }
 
-    fn get_parent_by_offset(&mut self, seekoffset: usize) -> Option<*mut Node> {
-        if self.offset == seekoffset {
-            return self.parent;
-        } else if seekoffset < self.offset {
-            if let Some(p) = self.parent {
-                unsafe {
-                    return (*p).get_parent_by_offset(seekoffset);
-                }
+    fn random_targets(r: &mut Lcg, depth: usize, count: &mut usize) -> Vec<Target> {
+        let mut vec = Vec::new();
+        let width = if depth == 0 { 0 } else { r.next(6) };
+        for _ in 0..width {
+            if *count == 0 {
+                break;
+            }
+            *count -= 1;
+            let mut t = Target::new(format!("k{}", r.next(4)));
+            for i in 0..r.next(3) {
+                t.text.push(format!("t{}\nline{}", *count, i));
+            }
+            t.value = random_targets(r, depth - 1, count);
+            vec.push(t);
+        }
+        vec
+    }
+
+    fn ordered() -> ParseOptions {
+        let mut options = ParseOptions::new();
+        options.ordered = true;
+        options
+    }
+
+    #[test]
+    fn test_deep() {
+        let depth = 1000;
+        let mut s = String::new();
+        for i in 0..depth {
+            s.push_str(&format!("{:x}~k:{}\n", i, i));
+        }
+        for i in (1..depth).rev() {
+            s.push_str(&format!("{:x}~j:{}\n", i, i));
+        }
+        let t = try_chars_to_target(s.chars()).unwrap();
+        let mut target = &t[0];
+        for i in 1..depth {
+            assert_eq!(target.value.len(), 2);
+            assert_eq!(target.value[1].text, vec![i.to_string()]);
+            target = &target.value[0];
+            assert_eq!(target.text, vec![i.to_string()]);
+        }
+        assert!(target.value.is_empty());
+    }
+
+    #[test]
+    fn test_wide() {
+        let width = 100_000;
+        let mut s = String::new();
+        for i in 0..width {
+            s.push_str(&format!("0~k{}:{}\n1~c:{}\n", i % 100, i, i));
+        }
+        let t = try_chars_to_target(s.chars()).unwrap();
+        assert_eq!(t.len(), 100);
+        assert_eq!(t[7].text.len(), width / 100);
+        assert_eq!(t[7].value.len(), width / 100);
+        assert_eq!(t[7].value[3].text, vec!["307"]);
+
+        let t = chars_to_document_with(s.chars(), &ordered())
+            .unwrap()
+            .targets;
+        assert_eq!(t.len(), width);
+        assert_eq!(t[width - 1].value[0].text, vec![(width - 1).to_string()]);
+
+        //distinct keys
+        let s: String = (0..width).map(|i| format!("0~k{}:{}\n", i, i)).collect();
+        let t = try_chars_to_target(s.chars()).unwrap();
+        assert_eq!(t.len(), width);
+        assert_eq!(t[width - 1].text, vec![(width - 1).to_string()]);
+    }
+
+    #[test]
+    fn test_random_round_trip() {
+        let mut r = Lcg(7);
+        for _ in 0..50 {
+            let mut count = 2000;
+            let mut vec = Vec::new();
+            while count > 0 {
+                let mut t = Target::new(format!("k{}", r.next(4)));
+                t.value = random_targets(&mut r, 8, &mut count);
+                count = count.saturating_sub(1);
+                vec.push(t);
             }
+            let s = vec_to_text(&vec).unwrap();
+            let d = chars_to_document_with(s.chars(), &ordered()).unwrap();
+            assert_eq!(d.targets, vec);
         }
-        None
     }
 }
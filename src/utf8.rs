@@ -0,0 +1,80 @@
+use std::mem::take;
+
+//byte order mark
+const BOM: char = '\u{FEFF}';
+
+//incremental UTF-8 decoder, a char may be split between chunks.
+//f receives each char, or None for each invalid sequence
+pub(crate) struct Utf8Decoder {
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl Utf8Decoder {
+    pub(crate) fn new() -> Self {
+        Utf8Decoder {
+            pending: Vec::new(),
+            started: false,
+        }
+    }
+
+    pub(crate) fn decode(&mut self, bytes: &[u8], f: &mut impl FnMut(Option<char>)) {
+        if self.pending.is_empty() {
+            self.decode_slice(bytes, f);
+        } else {
+            let mut buf = take(&mut self.pending);
+            buf.extend_from_slice(bytes);
+            self.decode_slice(&buf, f);
+        }
+    }
+
+    //end of input, an incomplete char is invalid
+    pub(crate) fn finish(&mut self, f: &mut impl FnMut(Option<char>)) {
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            f(None);
+        }
+        self.started = false;
+    }
+
+    fn decode_slice(&mut self, mut bytes: &[u8], f: &mut impl FnMut(Option<char>)) {
+        loop {
+            match std::str::from_utf8(bytes) {
+                Ok(s) => {
+                    self.chars(s, f);
+                    return;
+                }
+                Err(e) => {
+                    let (valid, rest) = bytes.split_at(e.valid_up_to());
+                    if let Ok(s) = std::str::from_utf8(valid) {
+                        self.chars(s, f);
+                    }
+                    match e.error_len() {
+                        Some(n) => {
+                            self.started = true;
+                            f(None);
+                            bytes = &rest[n..];
+                        }
+                        None => {
+                            self.pending.extend_from_slice(rest);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn chars(&mut self, s: &str, f: &mut impl FnMut(Option<char>)) {
+        let mut chars = s.chars();
+        if !self.started && !s.is_empty() {
+            self.started = true;
+            if s.starts_with(BOM) {
+                chars.next();
+            }
+        }
+        for c in chars {
+            f(Some(c));
+        }
+    }
+}
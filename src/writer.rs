@@ -0,0 +1,141 @@
+use crate::Target;
+use std::io::{Error, ErrorKind, Result, Write};
+
+//'~'
+const TILDE: char = '~';
+//':'
+const COLON: char = ':';
+//'|'
+const VERTICAL: char = '|';
+//'+'
+const PLUS: char = '+';
+//'\'
+const BACKSLASH: char = '\\';
+//'\r'
+const CR: char = '\r';
+//'\n'
+const LF: char = '\n';
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+//backslash before colon and backslash
+pub(crate) fn push_key(s: &mut String, key: &str) -> Result<()> {
+    if key.is_empty() {
+        return Err(invalid("empty key".to_string()));
+    }
+    for c in key.chars() {
+        if c == CR || c == LF || c == '\0' {
+            return Err(invalid(format!("key {:?} contains {:?}", key, c)));
+        }
+        if c == COLON || c == BACKSLASH {
+            s.push(BACKSLASH);
+        }
+        s.push(c);
+    }
+    Ok(())
+}
+
+//vertical after every line break
+pub(crate) fn push_text(s: &mut String, text: &str) -> Result<()> {
+    let mut iter = text.chars().peekable();
+    while let Some(c) = iter.next() {
+        if c == '\0' {
+            return Err(invalid(format!("text {:?} contains {:?}", text, c)));
+        }
+        s.push(c);
+        if c == LF || (c == CR && iter.peek() != Some(&LF)) {
+            s.push(VERTICAL);
+        }
+    }
+    Ok(())
+}
+
+//depth first with a stack, children pushed in reverse
+fn push_target(s: &mut String, target: &Target, offset: usize) -> Result<()> {
+    let mut stack = vec![(target, offset)];
+    while let Some((target, offset)) = stack.pop() {
+        s.push_str(&format!("{:x}", offset));
+        s.push(TILDE);
+        push_key(s, &target.name)?;
+        for (i, t) in target.text.iter().enumerate() {
+            if i == 0 {
+                s.push(COLON);
+            } else {
+                s.push(LF);
+                s.push(PLUS);
+            }
+            push_text(s, t)?;
+        }
+        s.push(LF);
+        stack.extend(target.value.iter().rev().map(|t| (t, offset + 1)));
+    }
+    Ok(())
+}
+
+///Convert `Target` to text format.
+///
+///Fails if a key is empty or contains a line break, or if a key or text contains `'\0'`.
+pub fn to_text(target: &Target) -> Result<String> {
+    let mut s = String::new();
+    push_target(&mut s, target, 0)?;
+    Ok(s)
+}
+
+///Convert `Vec<Target>` to text format, so that `chars_to_target` gives it back.
+///
+///Empty strings in `text` are not kept by the parser, so they are lost on the way back.
+pub fn vec_to_text(vec: &[Target]) -> Result<String> {
+    let mut s = String::new();
+    for target in vec {
+        push_target(&mut s, target, 0)?;
+    }
+    Ok(s)
+}
+
+///Write `Vec<Target>` in text format.
+pub fn write_to<W: Write>(vec: &[Target], mut w: W) -> Result<()> {
+    let mut s = String::new();
+    for target in vec {
+        s.clear();
+        push_target(&mut s, target, 0)?;
+        w.write_all(s.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::try_chars_to_target;
+
+    #[test]
+    fn test_round_trip() {
+        let s = "0~a:a
++a
+|b
+|c
+0~b:a
+1~a
+2~a:b
+0~c:
+|
+|cc
+|
+0~d\\:\\\\:x\r\n|y\r|z
+";
+        let t = try_chars_to_target(s.chars()).unwrap();
+        let text = vec_to_text(&t).unwrap();
+        assert!(text.starts_with("0~a:a\n+a\n|b\n|c\n0~b:a\n1~a\n2~a:b\n0~c:\n|\n|cc\n|\n"));
+        let r = try_chars_to_target(text.chars()).unwrap();
+        assert_eq!(t, r);
+        assert_eq!(r[3].name, "d:\\");
+        assert_eq!(r[3].text[0], "x\r\ny\rz");
+
+        let mut buf = Vec::new();
+        write_to(&t, &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), text);
+        assert!(to_text(&Target::new(String::new())).is_err());
+    }
+}
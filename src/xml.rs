@@ -1,17 +1,173 @@
-fn start_tag(s: &mut String, tag_name: &str) {
-    s.push('<');
-    s.push_str(tag_name);
-    s.push('>');
+use crate::options::{LineEnding, OutputOptions};
+use std::borrow::Cow;
+
+//chars that XML 1.0 cannot represent, even as character reference
+fn is_xml_char(c: char) -> bool {
+    !matches!(c, '\u{0}'..='\u{8}' | '\u{B}' | '\u{C}' | '\u{E}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}')
+}
+
+//NameStartChar of XML 1.0
+fn is_name_start_char(c: char) -> bool {
+    //colon(:) is left out, a prefix would need a namespace declaration
+    matches!(c,
+        'A'..='Z'
+        | '_'
+        | 'a'..='z'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}'
+    )
+}
+
+//NameChar of XML 1.0
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c)
+        || matches!(c,
+            '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}'
+        )
+}
+
+//invalid element name is renamed: invalid char to underscore(_), invalid first char gets an underscore(_) before it
+pub(crate) fn element_name(name: &str) -> Cow<'_, str> {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if is_name_start_char(c) && chars.all(is_name_char) => Cow::Borrowed(name),
+        _ => {
+            let mut s = String::new();
+            if !name.starts_with(is_name_start_char) {
+                s.push('_');
+            }
+            for c in name.chars() {
+                s.push(if is_name_char(c) { c } else { '_' });
+            }
+            Cow::Owned(s)
+        }
+    }
+}
+
+//invalid attribute name is renamed: whitespace, control, quote, less-than(<), greater-than(>),
+//solidus(/) and equals(=) chars to underscore(_), empty name to underscore(_)
+pub(crate) fn attribute_name(name: &str) -> Cow<'_, str> {
+    let invalid = |c: char| {
+        c.is_whitespace() || c.is_control() || matches!(c, '"' | '\'' | '<' | '>' | '/' | '=')
+    };
+    if name.is_empty() {
+        Cow::Borrowed("_")
+    } else if name.contains(invalid) {
+        Cow::Owned(
+            name.chars()
+                .map(|c| if invalid(c) { '_' } else { c })
+                .collect(),
+        )
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+//escape character data
+pub(crate) fn push_text(s: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => s.push_str("&amp;"),
+            '<' => s.push_str("&lt;"),
+            '>' => s.push_str("&gt;"),
+            '\r' => s.push_str("&#13;"),
+            c if !is_xml_char(c) => s.push('\u{FFFD}'),
+            c => s.push(c),
+        }
+    }
+}
+
+//escape attribute value in double quotes
+fn push_attribute_value(s: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '&' => s.push_str("&amp;"),
+            '<' => s.push_str("&lt;"),
+            '"' => s.push_str("&quot;"),
+            '\t' => s.push_str("&#9;"),
+            '\n' => s.push_str("&#10;"),
+            '\r' => s.push_str("&#13;"),
+            c if !is_xml_char(c) => s.push('\u{FFFD}'),
+            c => s.push(c),
+        }
+    }
+}
+
+//position of a tag in the output
+#[derive(Clone, Copy)]
+pub(crate) struct Layout<'a> {
+    pub options: &'a OutputOptions,
+    pub depth: usize,
+}
+
+impl<'a> Layout<'a> {
+    pub fn new(options: &'a OutputOptions) -> Self {
+        Layout { options, depth: 0 }
+    }
+
+    pub fn child(self) -> Self {
+        Layout {
+            depth: self.depth + 1,
+            ..self
+        }
+    }
+
+    //compact layout, for content that must stay on one line
+    pub fn compact() -> Layout<'static> {
+        const COMPACT: OutputOptions = OutputOptions {
+            indent: None,
+            line_ending: LineEnding::Lf,
+            inline_text: None,
+            wrap_attributes: None,
+        };
+        Layout {
+            options: &COMPACT,
+            depth: 0,
+        }
+    }
+
+    fn push_indent(self, s: &mut String, depth: usize) {
+        for _ in 0..self.options.indent.unwrap_or(0) * depth {
+            s.push(' ');
+        }
+    }
+
+    pub fn start_line(self, s: &mut String) {
+        self.push_indent(s, self.depth);
+    }
+
+    pub fn end_line(self, s: &mut String) {
+        if self.options.indent.is_some() {
+            s.push_str(self.options.line_ending.as_str());
+        }
+    }
 }
 
-fn start_tag_attribute(s: &mut String, tag_name: &str, attribute: &str) {
+fn start_tag_attribute(s: &mut String, layout: Layout, tag_name: &str, attributes: &[String]) {
     s.push('<');
-    s.push_str(tag_name);
-    if attribute.len() > 0 {
-        if !attribute.starts_with(' ') {
+    s.push_str(&element_name(tag_name));
+    let wrap = layout
+        .options
+        .wrap_attributes
+        .is_some_and(|n| attributes.len() > n);
+    for a in attributes {
+        if wrap {
+            s.push_str(layout.options.line_ending.as_str());
+            layout.push_indent(s, layout.depth + 1);
+        } else {
             s.push(' ');
         }
-        s.push_str(attribute);
+        s.push_str(a);
     }
     s.push('>');
 }
@@ -19,40 +175,172 @@ fn start_tag_attribute(s: &mut String, tag_name: &str, attribute: &str) {
 fn end_tag(s: &mut String, tag_name: &str) {
     s.push('<');
     s.push('/');
-    s.push_str(tag_name);
+    s.push_str(&element_name(tag_name));
     s.push('>');
 }
 
 //add xml format attribute
-pub(crate) fn add_attribute(s: &mut String, attribute_name: &str, attribute_value: &[String]) {
-    s.push(' ');
-    s.push_str(attribute_name);
+pub(crate) fn add_attribute(attributes: &mut Vec<String>, name: &str, attribute_value: &[String]) {
+    let mut s = String::new();
+    s.push_str(&attribute_name(name));
     s.push('=');
     s.push('\"');
     let mut value = String::new();
     for v in attribute_value {
-        value.push_str(&v);
+        value.push_str(v);
         value.push(' ');
     }
     value.pop();
-    s.push_str(&value);
+    push_attribute_value(&mut s, &value);
     s.push('\"');
+    attributes.push(s);
 }
 
 //add xml format tag
-pub(crate) fn add_tag(s: &mut String, tag_name: &str, text: &str) {
-    start_tag(s, tag_name);
-    if text.len() > 0 {
-        s.push_str(text);
+pub(crate) fn add_tag(s: &mut String, layout: Layout, tag_name: &str, text: &str) {
+    let mut content = String::new();
+    push_text(&mut content, text);
+    add_tag_with_content(s, layout, tag_name, &[], &content);
+}
+
+//add start tag only, for element without end tag
+pub(crate) fn add_start_tag(s: &mut String, layout: Layout, tag_name: &str, attributes: &[String]) {
+    layout.start_line(s);
+    start_tag_attribute(s, layout, tag_name, attributes);
+    layout.end_line(s);
+}
+
+//add tag with escaped content on the line of its tags, or on its own line when long
+pub(crate) fn add_tag_with_content(
+    s: &mut String,
+    layout: Layout,
+    tag_name: &str,
+    attributes: &[String],
+    content: &str,
+) {
+    layout.start_line(s);
+    start_tag_attribute(s, layout, tag_name, attributes);
+    let long = layout.options.indent.is_some()
+        && layout
+            .options
+            .inline_text
+            .is_some_and(|n| content.chars().count() > n);
+    if long {
+        layout.end_line(s);
+        layout.child().start_line(s);
+        s.push_str(content);
+        layout.end_line(s);
+        layout.start_line(s);
+    } else {
+        s.push_str(content);
     }
     end_tag(s, tag_name);
+    layout.end_line(s);
 }
 
-//add xml format tag with attribute
-pub(crate) fn add_tag_with_attribute(s: &mut String, tag_name: &str, attribute: &str, text: &str) {
-    start_tag_attribute(s, tag_name, attribute);
-    if text.len() > 0 {
-        s.push_str(text);
+//add xml format tag with attribute, children are rendered with the child layout
+pub(crate) fn add_tag_with_attribute(
+    s: &mut String,
+    layout: Layout,
+    tag_name: &str,
+    attributes: &[String],
+    children: &str,
+) {
+    layout.start_line(s);
+    start_tag_attribute(s, layout, tag_name, attributes);
+    if !children.is_empty() {
+        layout.end_line(s);
+        s.push_str(children);
+        layout.start_line(s);
     }
     end_tag(s, tag_name);
+    layout.end_line(s);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::Converter;
+    use crate::{
+        try_chars_to_target, vec_to_xml, vec_to_xml_with, LineEnding, OutputOptions, Target,
+    };
+
+    #[test]
+    fn test_escape() {
+        let mut a = Target::new("a b".to_string());
+        a.text.push("x < y && \"z\" > 1\r\n\u{1}".to_string());
+        let mut c = Target::new("1c".to_string());
+        c.text.push("]]>".to_string());
+        a.value.push(c);
+        let xml = vec_to_xml(vec![a, Target::new("d".to_string())]);
+        assert_eq!(
+            xml,
+            "<a_b>x &lt; y &amp;&amp; \"z\" &gt; 1&#13;\n\u{FFFD}</a_b><a_b><_1c>]]&gt;</_1c></a_b>"
+        );
+        let doc = format!("<root>{}</root>", xml);
+        let doc = roxmltree::Document::parse(&doc).unwrap();
+        let e = doc.root_element().first_element_child().unwrap();
+        assert_eq!(e.tag_name().name(), "a_b");
+        assert_eq!(e.text(), Some("x < y && \"z\" > 1\r\n\u{FFFD}"));
+        let mut a = Target::new("a:b".to_string());
+        a.text.push("1".to_string());
+        let xml = vec_to_xml(vec![a]);
+        assert_eq!(xml, "<a_b>1</a_b>");
+        assert!(roxmltree::Document::parse(&xml).is_ok());
+        let names = [
+            "\u{AA}",
+            "\u{B5}",
+            "a\u{B2}",
+            "\u{B7}a",
+            "a\u{B7}\u{301}",
+            "\u{E9}t\u{E9}",
+        ];
+        let vec = names
+            .iter()
+            .map(|n| {
+                let mut t = Target::new(n.to_string());
+                t.text.push("1".to_string());
+                t
+            })
+            .collect();
+        let xml = vec_to_xml(vec);
+        assert_eq!(
+            xml,
+            "<__>1</__><__>1</__><a_>1</a_><_\u{B7}a>1</_\u{B7}a><a\u{B7}\u{301}>1</a\u{B7}\u{301}>\
+             <\u{E9}t\u{E9}>1</\u{E9}t\u{E9}>"
+        );
+        let doc = format!("<root>{}</root>", xml);
+        assert!(roxmltree::Document::parse(&doc).is_ok());
+
+        let mut a = Vec::new();
+        super::add_attribute(&mut a, "title", &["\"a\" & <b>\n".to_string()]);
+        let doc = format!("<e {}/>", a[0]);
+        let doc = roxmltree::Document::parse(&doc).unwrap();
+        assert_eq!(doc.root_element().attribute("title"), Some("\"a\" & <b>\n"));
+    }
+
+    #[test]
+    fn test_pretty() {
+        let t = try_chars_to_target("0~a:x\n0~b\n1~c:long text\n1~d\n2~e:y\n".chars()).unwrap();
+        assert_eq!(
+            vec_to_xml(t.clone()),
+            vec_to_xml_with(t.clone(), &OutputOptions::new())
+        );
+        let mut options = OutputOptions::pretty(2);
+        options.inline_text = Some(5);
+        assert_eq!(
+            vec_to_xml_with(t, &options),
+            "<a>x</a>\n<b>\n  <c>\n    long text\n  </c>\n  <d>\n    <e>y</e>\n  </d>\n</b>\n"
+        );
+
+        let t =
+            try_chars_to_target("0~div\n1~id:a\n1~class:b\n1~p\n2~#text:x \n2~b:y\n1~br\n".chars())
+                .unwrap();
+        let mut options = OutputOptions::pretty(1);
+        options.line_ending = LineEnding::CrLf;
+        options.wrap_attributes = Some(1);
+        assert_eq!(
+            Converter::new().convert_with(t[0].clone(), &options),
+            "<div\r\n id=\"a\"\r\n class=\"b\">\r\n <p>x <b>y</b></p>\r\n <br>\r\n</div>\r\n"
+        );
+    }
 }
@@ -0,0 +1,479 @@
+use crate::options::MAX_DEPTH;
+use crate::Target;
+use std::collections::HashMap;
+use std::fmt;
+
+///What to do with attributes when parse xml.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum AttributePolicy {
+    ///Drop attributes.
+    #[default]
+    Ignore,
+    ///Attribute becomes a child target before the child elements, like `html::Converter` expects.
+    Children,
+    ///Like `Children`, with the prefix before the attribute name, e.g. `@`.
+    Prefixed(String),
+}
+
+///Options for parsing xml to `Vec<Target>`.
+#[derive(Clone, Debug)]
+pub struct XmlReadOptions {
+    pub attributes: AttributePolicy,
+    ///Keep every element as its own `Target` in source order,
+    ///instead of grouping sibling elements with the same name into one `Target`.
+    pub ordered: bool,
+    ///Levels of element nesting, like `Limits.max_depth`. The default is 1024.
+    pub max_depth: Option<usize>,
+}
+
+impl Default for XmlReadOptions {
+    fn default() -> Self {
+        XmlReadOptions {
+            attributes: AttributePolicy::default(),
+            ordered: false,
+            max_depth: Some(MAX_DEPTH),
+        }
+    }
+}
+
+impl XmlReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+///Error when parse xml.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XmlError {
+    pub message: String,
+    ///Row number, starting at 1.
+    pub row: usize,
+    ///Column number in chars, starting at 1.
+    pub column: usize,
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.message, self.row, self.column)
+    }
+}
+
+impl std::error::Error for XmlError {}
+
+///Parse xml to `Vec<Target>`, the inverse of `vec_to_xml`.
+pub fn xml_to_target(s: &str) -> Result<Vec<Target>, XmlError> {
+    xml_to_target_with(s, &XmlReadOptions::default())
+}
+
+///Parse xml to `Vec<Target>` with options.
+///
+///Element name becomes `Target.name`. An element without child elements has its character data
+///as one text, otherwise its child elements become `value` and only text that is not whitespace is kept.
+///Several root elements are allowed. Comments, processing instructions and doctype are skipped.
+pub fn xml_to_target_with(s: &str, options: &XmlReadOptions) -> Result<Vec<Target>, XmlError> {
+    XmlParser {
+        chars: s.chars().collect(),
+        i: 0,
+        options,
+    }
+    .parse()
+}
+
+//open element
+struct Frame {
+    name: String,
+    attributes: Vec<Target>,
+    children: Children,
+    texts: Vec<String>,
+    has_element: bool,
+}
+
+impl Frame {
+    fn new(name: String) -> Self {
+        Frame {
+            name,
+            attributes: Vec::new(),
+            children: Children::default(),
+            texts: vec![String::new()],
+            has_element: false,
+        }
+    }
+
+    fn text(&mut self) -> &mut String {
+        self.texts.last_mut().expect("texts is never empty")
+    }
+
+    fn into_target(mut self) -> Target {
+        let mut t = Target::new(self.name);
+        if self.has_element {
+            t.text = self
+                .texts
+                .into_iter()
+                .filter(|s| !s.chars().all(char::is_whitespace))
+                .collect();
+        } else {
+            t.text = vec![self.texts.concat()];
+        }
+        t.value = self.attributes;
+        t.value.append(&mut self.children.targets);
+        t
+    }
+}
+
+//child elements, indexed by name for grouping
+#[derive(Default)]
+struct Children {
+    targets: Vec<Target>,
+    index: HashMap<String, usize>,
+}
+
+impl Children {
+    fn add(&mut self, mut target: Target, ordered: bool) {
+        if !ordered {
+            match self.index.get(&target.name) {
+                Some(&i) => {
+                    let t = &mut self.targets[i];
+                    t.text.append(&mut target.text);
+                    t.value.append(&mut target.value);
+                    return;
+                }
+                None => {
+                    self.index.insert(target.name.clone(), self.targets.len());
+                }
+            }
+        }
+        self.targets.push(target);
+    }
+}
+
+struct XmlParser<'a> {
+    chars: Vec<char>,
+    i: usize,
+    options: &'a XmlReadOptions,
+}
+
+impl XmlParser<'_> {
+    fn error(&self, message: &str) -> XmlError {
+        let mut row = 1;
+        let mut column = 1;
+        for c in &self.chars[..self.i.min(self.chars.len())] {
+            if *c == '\n' {
+                row += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        XmlError {
+            message: message.to_string(),
+            row,
+            column,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.i).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(n, c)| self.chars.get(self.i + n) == Some(&c))
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<String, XmlError> {
+        let start = self.i;
+        while self.i < self.chars.len() {
+            if self.starts_with(end) {
+                let s = self.chars[start..self.i].iter().collect();
+                self.i += end.chars().count();
+                return Ok(s);
+            }
+            self.i += 1;
+        }
+        Err(self.error(&format!("missing {:?}", end)))
+    }
+
+    fn space(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.i += 1;
+        }
+    }
+
+    fn name(&mut self) -> Result<String, XmlError> {
+        let start = self.i;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || matches!(c, '/' | '>' | '=' | '<' | '"' | '\'') {
+                break;
+            }
+            self.i += 1;
+        }
+        if self.i == start {
+            return Err(self.error("expected a name"));
+        }
+        Ok(self.chars[start..self.i].iter().collect())
+    }
+
+    //after '&'
+    fn reference(&mut self) -> Result<char, XmlError> {
+        let start = self.i;
+        let name = self.skip_past(";")?;
+        let c = match name.as_str() {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            n => {
+                let code = if let Some(h) = n.strip_prefix("#x") {
+                    u32::from_str_radix(h, 16).ok()
+                } else if let Some(d) = n.strip_prefix('#') {
+                    d.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+            }
+        };
+        c.ok_or_else(|| {
+            self.i = start;
+            self.error(&format!("unknown reference &{};", name))
+        })
+    }
+
+    fn attribute_value(&mut self) -> Result<String, XmlError> {
+        let quote = match self.peek() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return Err(self.error("expected a quoted value")),
+        };
+        self.i += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                Some(c) if c == quote => {
+                    self.i += 1;
+                    return Ok(s);
+                }
+                Some('&') => {
+                    self.i += 1;
+                    s.push(self.reference()?);
+                }
+                Some('<') => return Err(self.error("'<' in attribute value")),
+                Some('\t' | '\n' | '\r') => {
+                    if self.starts_with("\r\n") {
+                        self.i += 1;
+                    }
+                    self.i += 1;
+                    s.push(' ');
+                }
+                Some(c) => {
+                    self.i += 1;
+                    s.push(c);
+                }
+                None => return Err(self.error("unterminated attribute value")),
+            }
+        }
+    }
+
+    fn attribute(&self, name: String, value: String) -> Option<Target> {
+        let name = match &self.options.attributes {
+            AttributePolicy::Ignore => return None,
+            AttributePolicy::Children => name,
+            AttributePolicy::Prefixed(p) => format!("{}{}", p, name),
+        };
+        let mut t = Target::new(name);
+        t.text.push(value);
+        Some(t)
+    }
+
+    fn parse(mut self) -> Result<Vec<Target>, XmlError> {
+        let mut roots = Children::default();
+        let mut stack: Vec<Frame> = Vec::new();
+        while let Some(c) = self.peek() {
+            if self.starts_with("<!--") {
+                self.i += 4;
+                self.skip_past("-->")?;
+            } else if self.starts_with("<?") {
+                self.i += 2;
+                self.skip_past("?>")?;
+            } else if self.starts_with("<![CDATA[") {
+                self.i += 9;
+                let s = self.skip_past("]]>")?;
+                match stack.last_mut() {
+                    Some(f) => f.text().push_str(&s),
+                    None => return Err(self.error("text outside element")),
+                }
+            } else if self.starts_with("<!") {
+                self.skip_doctype()?;
+            } else if self.starts_with("</") {
+                let start = self.i;
+                self.i += 2;
+                let name = self.name()?;
+                self.space();
+                if self.peek() != Some('>') {
+                    return Err(self.error("expected '>'"));
+                }
+                match stack.pop() {
+                    Some(f) if f.name == name => {
+                        self.i += 1;
+                        let t = f.into_target();
+                        match stack.last_mut() {
+                            Some(p) => p.children.add(t, self.options.ordered),
+                            None => roots.add(t, self.options.ordered),
+                        }
+                    }
+                    _ => {
+                        self.i = start;
+                        return Err(self.error(&format!("unexpected end tag {}", name)));
+                    }
+                }
+            } else if c == '<' {
+                if self.options.max_depth.is_some_and(|m| stack.len() >= m) {
+                    return Err(self.error("elements are nested too deep"));
+                }
+                self.i += 1;
+                let mut f = Frame::new(self.name()?);
+                let empty = loop {
+                    self.space();
+                    match self.peek() {
+                        Some('>') => {
+                            self.i += 1;
+                            break false;
+                        }
+                        Some('/') if self.starts_with("/>") => {
+                            self.i += 2;
+                            break true;
+                        }
+                        Some(_) => {
+                            let n = self.name()?;
+                            self.space();
+                            if self.peek() != Some('=') {
+                                return Err(self.error("expected '='"));
+                            }
+                            self.i += 1;
+                            self.space();
+                            let v = self.attribute_value()?;
+                            f.attributes.extend(self.attribute(n, v));
+                        }
+                        None => return Err(self.error("unterminated start tag")),
+                    }
+                };
+                if let Some(p) = stack.last_mut() {
+                    p.has_element = true;
+                    p.texts.push(String::new());
+                }
+                if empty {
+                    let t = f.into_target();
+                    match stack.last_mut() {
+                        Some(p) => p.children.add(t, self.options.ordered),
+                        None => roots.add(t, self.options.ordered),
+                    }
+                } else {
+                    stack.push(f);
+                }
+            } else {
+                let c = if c == '&' {
+                    self.i += 1;
+                    self.reference()?
+                } else if c == '\r' {
+                    //line break normalization
+                    self.i += 1;
+                    if self.peek() == Some('\n') {
+                        self.i += 1;
+                    }
+                    '\n'
+                } else {
+                    self.i += 1;
+                    c
+                };
+                match stack.last_mut() {
+                    Some(f) => f.text().push(c),
+                    None if c.is_whitespace() => {}
+                    None => {
+                        self.i -= 1;
+                        return Err(self.error("text outside element"));
+                    }
+                }
+            }
+        }
+        match stack.last() {
+            Some(f) => Err(self.error(&format!("missing end tag {}", f.name))),
+            None => Ok(roots.targets),
+        }
+    }
+
+    //<!DOCTYPE ...> with optional [internal subset]
+    fn skip_doctype(&mut self) -> Result<(), XmlError> {
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            self.i += 1;
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => return Ok(()),
+                _ => {}
+            }
+        }
+        Err(self.error("unterminated declaration"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{try_chars_to_target, vec_to_xml};
+
+    #[test]
+    fn test_round_trip() {
+        let s = "0~a:a
++a
+|b
+0~b:x < y & \"z\"\r
+|w
+1~c:
+1~c:1
+2~d:2
+0~e
+";
+        let t = try_chars_to_target(s.chars()).unwrap();
+        let xml = vec_to_xml(t.clone());
+        let r = xml_to_target(&xml).unwrap();
+        assert_eq!(r[0], t[0]);
+        assert_eq!(vec_to_xml(r), xml);
+    }
+
+    #[test]
+    fn test_parse() {
+        let xml = "<?xml version=\"1.0\"?>
+<!DOCTYPE a [<!ENTITY x \"y\">]>
+<!-- comment -->
+<a id='1' class=\"p&amp;q\r\nr\">
+  <b>one<![CDATA[<two>]]>&#x33;</b>
+  <c/>
+  text
+  <b>four</b>
+</a>";
+        let t = xml_to_target(xml).unwrap();
+        assert_eq!(t[0].text, vec!["\n  text\n  "]);
+        assert_eq!(t[0].value[0].text, vec!["one<two>3", "four"]);
+        assert_eq!(t[0].value[1].text, vec![""]);
+
+        let mut options = XmlReadOptions::new();
+        options.attributes = AttributePolicy::Prefixed("@".to_string());
+        options.ordered = true;
+        let t = xml_to_target_with(xml, &options).unwrap();
+        let names: Vec<&str> = t[0].value.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["@id", "@class", "b", "c", "b"]);
+        assert_eq!(t[0].value[1].text, vec!["p&q r"]);
+
+        let e = xml_to_target("<a>\n<b></a>").unwrap_err();
+        assert_eq!((e.row, e.column), (2, 4));
+        assert!(xml_to_target("<a>&nope;</a>").is_err());
+        assert!(xml_to_target("<a>").is_err());
+        //many distinct siblings
+        let children: String = (0..50_000).map(|i| format!("<c{}/><c{}/>", i, i)).collect();
+        let t = xml_to_target(&format!("<r>{}</r>", children)).unwrap();
+        assert_eq!(t[0].value.len(), 50_000);
+        assert_eq!(t[0].value[9].text, vec!["", ""]);
+    }
+}
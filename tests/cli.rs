@@ -0,0 +1,115 @@
+#![cfg(feature = "json")]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+//run the binary with input on stdin, returns exit code, stdout and stderr
+fn run(args: &[&str], input: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_objnor"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    (
+        out.status.code().unwrap(),
+        String::from_utf8(out.stdout).unwrap(),
+        String::from_utf8(out.stderr).unwrap(),
+    )
+}
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("objnor-cli-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_usage() {
+    let (code, out, err) = run(&[], "");
+    assert_eq!((code, out.as_str()), (2, ""));
+    assert!(err.starts_with("missing command\nusage:"));
+    let (code, _, err) = run(&["fmt", "--help"], "");
+    assert_eq!(code, 2);
+    assert!(err.starts_with("usage:"));
+    let (code, _, err) = run(&["nope"], "");
+    assert_eq!(code, 2);
+    assert!(err.starts_with("unknown command nope"));
+    let (code, _, err) = run(&["fmt", "--nope"], "");
+    assert_eq!(code, 2);
+    assert!(err.starts_with("unknown option --nope"));
+    let (code, _, err) = run(&["fmt", "a", "b"], "");
+    assert_eq!(code, 2);
+    assert!(err.starts_with("fmt accepts one file"));
+    let (code, _, err) = run(&["to-xml", "--indent", "x"], "");
+    assert_eq!(code, 2);
+    assert!(err.starts_with("invalid indent x"));
+}
+
+#[test]
+fn test_check() {
+    let good_path = temp_file("good.on", "0~a:1\n1~b\n");
+    let bad_path = temp_file("bad.on", "0~a\n2~b\n0~:c\n");
+    let (good, bad) = (good_path.to_str().unwrap(), bad_path.to_str().unwrap());
+    assert_eq!(run(&["check", good], ""), (0, String::new(), String::new()));
+    let (code, out, err) = run(&["check", good, bad], "");
+    assert_eq!((code, out.as_str()), (1, ""));
+    assert_eq!(
+        err,
+        format!(
+            "{0}:2:2: offset jumped by more than one\n{0}:3:3: key is empty\n",
+            bad
+        )
+    );
+    let (code, _, err) = run(&["check", "--strict", bad], "");
+    assert_eq!(code, 1);
+    assert_eq!(
+        err,
+        format!("{}:2:2: offset jumped by more than one\n", bad)
+    );
+    let (code, _, err) = run(&["check"], "0~a\n0~:b\n");
+    assert_eq!(code, 1);
+    assert_eq!(err, "<stdin>:2:3: key is empty\n");
+    let (code, _, err) = run(&["check", "missing-file.on"], "");
+    assert_eq!(code, 1);
+    assert!(err.starts_with("missing-file.on: "));
+    std::fs::remove_file(&good_path).unwrap();
+    std::fs::remove_file(&bad_path).unwrap();
+}
+
+#[test]
+fn test_commands() {
+    let s = "0~b:x  \r\n  |y\n0~a:1\n+2\n";
+    assert_eq!(run(&["fmt"], s).1, "0~b:x\n|y\n0~a:1\n+2\n");
+    assert_eq!(run(&["fmt", "--sort"], s).1, "0~a:1\n+2\n0~b:x\n|y\n");
+    assert_eq!(run(&["fmt"], "0~b :1\n").1, "0~b :1\n");
+    let (code, out, err) = run(&["fmt", "--strict"], "0~a\n2~b\n0~:c\n");
+    assert_eq!((code, out.as_str()), (1, ""));
+    assert_eq!(err, "<stdin>:2:2: offset jumped by more than one\n");
+    assert_eq!(
+        run(&["to-xml"], "0~a:1\n1~b:2\n").1,
+        "<a>1</a><a><b>2</b></a>\n"
+    );
+    assert_eq!(
+        run(&["to-html", "-"], "0~p:1\n0~img\n1~src:a\n").1,
+        "<p>1</p><img src=\"a\">\n"
+    );
+    assert_eq!(
+        run(&["flatten", "-s", "/"], "0~a:1\n1~b:x\n|y\n").1,
+        "a=1\na/b=x\\ny\n"
+    );
+    let json = run(&["to-json"], "0~a:1\n1~b:2\n").1;
+    assert_eq!(run(&["from-json"], &json).1, "0~a:1\n1~b:2\n");
+    let (code, _, err) = run(&["from-json"], "{");
+    assert_eq!(code, 1);
+    assert!(err.starts_with("<stdin>:1:2: "));
+}